@@ -1,21 +1,26 @@
 use anyhow::{anyhow, Result};
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "logs")]
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use std::{
+    collections::BTreeMap,
     env, fs,
     io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    git,
-    util::{expand_path, get_dir, get_name, version_string, xdg_path},
+    config::{Encryption, Maintenance, Settings, Snapshots, Target},
+    crypto, git, limit,
+    util::{
+        civil_days, dir_size, expand_path, get_dir, get_name, timestamp, version_string, xdg_path,
+    },
 };
 
 const BANNER: &str = "\u{2584}\u{2584}\u{2584}\u{2584}\u{2584}\u{2584}\u{2584}\
@@ -40,7 +45,9 @@ const BAR_2: [&str; 3] = ["+", "+", "-"];
 #[derive(Default, Serialize, Deserialize)]
 struct Config {
     source_directory: String,
-    targets: Vec<String>,
+    targets: Vec<Target>,
+    #[serde(flatten)]
+    settings: Settings,
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     count: u64,
@@ -49,18 +56,21 @@ struct Config {
 impl Config {
     fn read(path: &PathBuf) -> Result<Self> {
         let config_data = fs::read_to_string(path)?;
-        let config: Self = serde_yaml::from_str(&config_data)?;
+        let mut config: Self = serde_yaml::from_str(&config_data)?;
         let config_count = config.targets.len().try_into().unwrap();
 
-        for target in &config.targets {
-            if target.ends_with('/') {
-                return Err(anyhow!("Invalid URL {} (trailing slash)", &target));
+        for target in &mut config.targets {
+            if target.url.ends_with('/') {
+                return Err(anyhow!("Invalid URL {} (trailing slash)", &target.url));
             }
+
+            target.resolve(&config.settings);
         }
 
         Ok(Self {
             source_directory: config.source_directory,
             targets: config.targets,
+            settings: config.settings,
             count: config_count,
         })
     }
@@ -79,7 +89,7 @@ impl Config {
 
             let source = source_prompt?;
 
-            let target: Vec<String> = target_prompt?
+            let target: Vec<Target> = target_prompt?
                 .split(',')
                 .map(|target| {
                     let mut target_string = String::from(target);
@@ -87,13 +97,14 @@ impl Config {
                         target_string.pop();
                     }
 
-                    target_string
+                    Target::new(target_string)
                 })
                 .collect();
 
             let config = Self {
                 source_directory: source,
                 targets: target,
+                settings: Settings::default(),
                 count: 0,
             };
 
@@ -111,10 +122,26 @@ impl Config {
         Ok(())
     }
 
+    // Subcommands only read settings, so a missing file never starts the wizard
+    fn open_existing(&mut self, matches: &ArgMatches) -> Result<()> {
+        if let Some(config_path) = matches.get_one::<PathBuf>("config") {
+            self.load_config(config_path)?;
+        } else {
+            let path = xdg_path()?;
+
+            if path.exists() {
+                self.load_config(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn load_config(&mut self, path: &PathBuf) -> Result<()> {
         let config = Self::read(path)?;
         self.source_directory = config.source_directory;
         self.targets = config.targets;
+        self.settings = config.settings;
         self.count = config.count;
 
         Ok(())
@@ -153,12 +180,23 @@ fn args() -> ArgMatches {
                 .long("config")
                 .value_name("FILE")
                 .help("Use alternative config file")
+                .global(true)
                 .value_parser(value_parser!(PathBuf)),
             Arg::new("backups")
                 .short('b')
                 .long("backups")
                 .help("Enable backups")
                 .action(ArgAction::SetTrue),
+            Arg::new("shared")
+                .long("shared")
+                .help("Share objects between source clones and backups")
+                .requires("backups")
+                .action(ArgAction::SetTrue),
+            Arg::new("maintenance")
+                .short('m')
+                .long("maintenance")
+                .help("Repack and prune repositories now")
+                .action(ArgAction::SetTrue),
             Arg::new("purge")
                 .short('p')
                 .long("purge")
@@ -174,7 +212,92 @@ fn args() -> ArgMatches {
                 .long("no-log")
                 .help("Disable logging")
                 .action(ArgAction::SetFalse),
-        ]);
+        ])
+        .subcommand(
+            Command::new("verify")
+                .about("Check the integrity of backup mirrors")
+                .arg(
+                    Arg::new("path")
+                        .action(ArgAction::Set)
+                        .value_name("PATH")
+                        .help("Backup destination")
+                        .value_parser(value_parser!(PathBuf))
+                        .hide_default_value(true)
+                        .default_value(get_dir()),
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Restore source clones from backup mirrors")
+                .args([
+                    Arg::new("path")
+                        .action(ArgAction::Set)
+                        .value_name("PATH")
+                        .help("Backup destination")
+                        .value_parser(value_parser!(PathBuf))
+                        .hide_default_value(true)
+                        .default_value(get_dir()),
+                    Arg::new("target")
+                        .short('t')
+                        .long("target")
+                        .value_name("NAME")
+                        .help("Restore only this repository")
+                        .action(ArgAction::Append),
+                    Arg::new("push")
+                        .long("push")
+                        .value_name("URL")
+                        .help("Push the mirror to a new remote instead")
+                        .requires("target"),
+                    Arg::new("bundles")
+                        .long("bundles")
+                        .value_name("DIR")
+                        .help("Rebuild missing mirrors from exported bundles first")
+                        .value_parser(value_parser!(PathBuf)),
+                ]),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export backup mirrors as git bundles")
+                .args([
+                    Arg::new("path")
+                        .action(ArgAction::Set)
+                        .value_name("PATH")
+                        .help("Backup destination")
+                        .value_parser(value_parser!(PathBuf))
+                        .hide_default_value(true)
+                        .default_value(get_dir()),
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("DIR")
+                        .help("Bundle destination")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                    Arg::new("incremental")
+                        .short('i')
+                        .long("incremental")
+                        .help("Only bundle objects added since the last export")
+                        .action(ArgAction::SetTrue),
+                    Arg::new("target")
+                        .short('t')
+                        .long("target")
+                        .value_name("NAME")
+                        .help("Export only this repository")
+                        .action(ArgAction::Append),
+                ]),
+        )
+        .subcommand(
+            Command::new("decrypt")
+                .about("Decrypt exported bundles and archives")
+                .arg(
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .help("Encrypted files (.enc)")
+                        .value_parser(value_parser!(PathBuf))
+                        .action(ArgAction::Append)
+                        .required(true),
+                ),
+        );
 
     matches.get_matches()
 }
@@ -200,16 +323,25 @@ fn cli(matches: &ArgMatches) -> Result<()> {
     let path = matches.get_one::<PathBuf>("path").unwrap();
     let purge = matches.get_flag("purge");
     let repo_mirror = matches.get_flag("backups");
+    let shared = matches.get_flag("shared");
+    let force_maintenance = matches.get_flag("maintenance");
     let silent = matches.get_flag("silent");
     #[cfg(feature = "logs")]
     let logs = matches.get_flag("logs");
     let mut config = Config::default();
+    let config_path = match matches.get_one::<PathBuf>("config") {
+        Some(config_path) => config_path.clone(),
+        None => xdg_path()?,
+    };
 
-    if let Some(config_path) = matches.get_one::<PathBuf>("config") {
-        config.open(config_path)?;
-    } else {
-        config.open(&xdg_path()?)?;
-    }
+    config.open(&config_path)?;
+    limit::set_rate(config.settings.rate_limit);
+
+    let maintenance = config
+        .settings
+        .maintenance
+        .clone()
+        .or_else(|| force_maintenance.then(Maintenance::default));
 
     #[cfg(feature = "logs")]
     if logs {
@@ -223,6 +355,12 @@ fn cli(matches: &ArgMatches) -> Result<()> {
 
     let mut err_count = 0;
     let mut compl_count = 0;
+    let mut orph_count = 0;
+    let mut total_reclaimed = None;
+    let run_started = Instant::now();
+    let started = timestamp(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+    let generation = started[..10].to_owned();
+    let mut records = Vec::new();
     let progress_bar = indicat.add(ProgressBar::new(config.count));
 
     progress_bar.set_style(indicat_template);
@@ -230,14 +368,14 @@ fn cli(matches: &ArgMatches) -> Result<()> {
 
     for target in config.targets {
         let spinner = indicat.insert_before(&progress_bar, ProgressBar::new_spinner());
-        let destination_clone = format!(
+        let mut destination_clone = format!(
             "{}/{}",
             PathBuf::from(expand_path(&config.source_directory)).display(),
-            get_name(&target)
+            get_name(&target.url)
         );
 
-        let destination_backup = format!("{}/{}.dorst", &path.display(), get_name(&target));
-        let target_name = get_name(&target);
+        let mut destination_backup = format!("{}/{}.dorst", &path.display(), get_name(&target.url));
+        let target_name = get_name(&target.url);
 
         if !silent {
             spinner.tick();
@@ -255,21 +393,129 @@ fn cli(matches: &ArgMatches) -> Result<()> {
             fs::remove_dir_all(&destination_backup)?;
         }
 
-        match process_repo(
+        let target_started = Instant::now();
+
+        git::take_received();
+
+        let result = process_repo(
             &destination_clone,
             &destination_backup,
             &target,
             repo_mirror,
+            shared,
             Some(&spinner),
             Some(silent),
-        ) {
-            Ok(_) => {
+        )
+        .and_then(|()| {
+            maintenance.as_ref().map_or(Ok(None), |maintenance| {
+                if !silent {
+                    spinner.set_message(format!(
+                        "\x1b[1;96mmaintenance\x1b[0m \x1b[93m{target_name}\x1b[0m"
+                    ));
+                }
+
+                maintain(
+                    &destination_clone,
+                    &destination_backup,
+                    maintenance,
+                    force_maintenance,
+                    repo_mirror,
+                )
+            })
+        })
+        .and_then(|reclaimed| {
+            if config.settings.rewrite_redirects {
+                rewrite_redirect(
+                    &config_path,
+                    &target.url,
+                    &mut destination_clone,
+                    &mut destination_backup,
+                    repo_mirror,
+                )?;
+            }
+
+            if let Some(archive) = &config.settings.archive {
+                if !silent {
+                    spinner.set_message(format!(
+                        "\x1b[1;96marchive\x1b[0m \x1b[93m{target_name}\x1b[0m"
+                    ));
+                }
+
+                let source = if repo_mirror {
+                    &destination_backup
+                } else {
+                    &destination_clone
+                };
+
+                let output = archive.directory.as_ref().map_or_else(
+                    || path.join("archives"),
+                    |directory| PathBuf::from(expand_path(directory)),
+                );
+
+                if let Some(file) = git::archive_tree(
+                    Path::new(source),
+                    &output,
+                    archive.format,
+                    config.settings.encryption.as_ref(),
+                )? {
+                    spinner.set_prefix(format!(
+                        "{} \x1b[37m(archived {})\x1b[0m",
+                        spinner.prefix(),
+                        file.file_name().unwrap().to_string_lossy()
+                    ));
+                }
+            }
+
+            if repo_mirror && config.settings.snapshots.is_some() {
+                let snapshot = path
+                    .join("snapshots")
+                    .join(&generation)
+                    .join(Path::new(&destination_backup).file_name().unwrap());
+
+                if git::snapshot_mirror(Path::new(&destination_backup), &snapshot)? {
+                    spinner.set_prefix(format!(
+                        "{} \x1b[37m(snapshot {generation})\x1b[0m",
+                        spinner.prefix()
+                    ));
+                }
+            }
+
+            Ok(reclaimed)
+        });
+
+        if config.settings.manifest.is_some() {
+            let repo = if repo_mirror {
+                &destination_backup
+            } else {
+                &destination_clone
+            };
+
+            records.push(TargetRecord::new(
+                &target.url,
+                &result,
+                Path::new(repo),
+                repo_mirror,
+                target_started,
+            ));
+        }
+
+        match result {
+            Ok(reclaimed) => {
                 #[cfg(feature = "logs")]
                 if logs {
                     info!("Completed: {target_name}");
                 }
 
                 compl_count += 1;
+                if let Some(reclaimed) = reclaimed {
+                    total_reclaimed = Some(total_reclaimed.unwrap_or_default() + reclaimed);
+                    spinner.set_prefix(format!(
+                        "{} \x1b[37m(reclaimed {})\x1b[0m",
+                        spinner.prefix(),
+                        HumanBytes(reclaimed)
+                    ));
+                }
+
                 if !silent {
                     let status = spinner.prefix();
                     let branch = git::current_branch(destination_clone.into())?;
@@ -279,6 +525,26 @@ fn cli(matches: &ArgMatches) -> Result<()> {
                 }
             }
 
+            Err(error) if error.is::<git::Orphaned>() => {
+                #[cfg(feature = "logs")]
+                if logs {
+                    warn!("Orphaned: {target_name} - {error}");
+                }
+
+                let orph = format!(
+                    "\x1b[1;33morphaned\x1b[0m \x1b[0;93m{target_name}\x1b[0m \x1b[37m{error}\x1b[0m"
+                );
+
+                orph_count += 1;
+                if !silent {
+                    if spinner.is_hidden() {
+                        println!("{}", &orph);
+                    }
+
+                    spinner.finish_with_message(orph);
+                }
+            }
+
             Err(error) => {
                 #[cfg(feature = "logs")]
                 if logs {
@@ -303,6 +569,53 @@ fn cli(matches: &ArgMatches) -> Result<()> {
 
     progress_bar.finish();
 
+    if let Some(reclaimed) = total_reclaimed.filter(|_| !silent) {
+        println!(
+            "\u{2523}\u{2578}\x1b[1mRECLAIMED\x1b[0m \x1b[37m{}\x1b[0m",
+            HumanBytes(reclaimed)
+        );
+    }
+
+    if let Some(snapshots) = config.settings.snapshots.as_ref().filter(|_| repo_mirror) {
+        let pruned = prune_snapshots(&path.join("snapshots"), snapshots)?;
+
+        if pruned > 0 && !silent {
+            println!("\u{2523}\u{2578}\x1b[1mSNAPSHOTS\x1b[0m \x1b[37m({pruned} pruned)\x1b[0m");
+        }
+    }
+
+    if let Some(manifest) = &config.settings.manifest {
+        let directory = manifest.directory.as_ref().map_or_else(
+            || path.join("manifests"),
+            |directory| PathBuf::from(expand_path(directory)),
+        );
+
+        let file = directory.join(format!("dorst-{}.yml", started.replace([':', '-'], "")));
+        let run = RunManifest {
+            started,
+            duration: seconds(run_started),
+            backups: repo_mirror,
+            targets: records,
+        };
+
+        fs::create_dir_all(&directory)?;
+        fs::write(&file, serde_yaml::to_string(&run)?)?;
+
+        if !silent {
+            println!(
+                "\u{2523}\u{2578}\x1b[1mMANIFEST\x1b[0m \x1b[37m{}\x1b[0m",
+                file.display()
+            );
+        }
+    }
+
+    if orph_count > 0 && !silent {
+        println!(
+            "\u{2523}\u{2578}\x1b[1mORPHANED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;93m{orph_count}\x1b[0m\x1b[37m)\x1b[0m"
+        );
+    }
+
     #[cfg(feature = "logs")]
     if logs {
         info!("Finished");
@@ -327,49 +640,641 @@ fn cli(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+#[derive(Serialize)]
+struct RunManifest {
+    started: String,
+    duration: f64,
+    backups: bool,
+    targets: Vec<TargetRecord>,
+}
+
+#[derive(Serialize)]
+struct TargetRecord {
+    url: String,
+    path: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration: f64,
+    received: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    head: Option<String>,
+    tips: BTreeMap<String, String>,
+}
+
+impl TargetRecord {
+    fn new<T>(url: &str, result: &Result<T>, repo: &Path, mirror: bool, started: Instant) -> Self {
+        let opened = git2::Repository::open(repo).ok();
+        let (status, error) = match result {
+            Ok(_) => ("ok", None),
+            Err(error) if error.is::<git::Orphaned>() => ("orphaned", Some(error.to_string())),
+            Err(error) => ("error", Some(error.to_string())),
+        };
+
+        Self {
+            url: url.to_owned(),
+            path: repo.display().to_string(),
+            status,
+            error,
+            duration: seconds(started),
+            received: git::take_received(),
+            size: (mirror && repo.exists()).then(|| dir_size(repo)),
+            head: opened.as_ref().and_then(|repo| {
+                repo.find_reference("HEAD")
+                    .ok()
+                    .and_then(|head| head.symbolic_target().map(String::from))
+            }),
+            tips: opened
+                .as_ref()
+                .and_then(|repo| git::ref_tips(repo).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn seconds(started: Instant) -> f64 {
+    (started.elapsed().as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+fn prune_snapshots(path: &Path, snapshots: &Snapshots) -> Result<usize> {
+    let names: Vec<String> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| civil_days(name).is_some())
+            .collect(),
+        Err(_) => return Ok(0),
+    };
+
+    let generations: Vec<&str> = names.iter().map(String::as_str).collect();
+    let keep = snapshots.retain(&generations);
+    let mut pruned = 0;
+
+    for generation in generations.iter().filter(|name| !keep.contains(name)) {
+        fs::remove_dir_all(path.join(generation))?;
+        pruned += 1;
+    }
+
+    Ok(pruned)
+}
+
 fn process_repo(
     destination_clone: &str,
     destination_backup: &str,
-    target: &str,
+    target: &Target,
     mirror: bool,
+    shared: bool,
     #[cfg(feature = "cli")] spinner: Option<&ProgressBar>,
     #[cfg(feature = "cli")] silent: Option<bool>,
 ) -> Result<()> {
-    git::process_target(
-        destination_clone,
-        target,
-        false,
-        spinner,
-        #[cfg(feature = "gui")]
-        &None,
-        silent,
-    )?;
+    let mut orphaned = None;
+    let mut errors = Vec::new();
+    let mut source = mirror.then_some(destination_backup);
 
     if mirror {
         if silent == Some(false) {
             spinner.unwrap().set_message(format!(
                 "\x1b[1;96mbackup \x1b[0;93m{}\x1b[0m",
-                get_name(target)
+                get_name(&target.url)
             ));
         }
 
-        git::process_target(
+        match git::process_target(
             destination_backup,
             target,
             true,
+            None,
             spinner,
             #[cfg(feature = "gui")]
             &None,
             silent,
+        ) {
+            Ok(()) => {}
+            // Orphaned clones are still refreshed from the preserved mirror
+            Err(error) if error.is::<git::Orphaned>() => orphaned = Some(error),
+            // The source clone is still updated, straight from upstream
+            Err(error) => {
+                errors.push(format!("backup: {error}"));
+                source = None;
+            }
+        }
+
+        for remote in target
+            .options
+            .push
+            .iter()
+            .filter(|_| source.is_some() && orphaned.is_none())
+        {
+            if silent == Some(false) {
+                spinner.unwrap().set_message(format!(
+                    "\x1b[1;96mpush \x1b[0;93m{}\x1b[0m \x1b[37m{}\x1b[0m",
+                    get_name(&target.url),
+                    remote.url
+                ));
+            }
+
+            // An unreachable remote does not hold back the source clone
+            if let Err(error) = git::push_mirror(Path::new(destination_backup), remote) {
+                errors.push(format!("push to {}: {error}", remote.url));
+            }
+        }
+    }
+
+    let result = git::process_target(
+        destination_clone,
+        target,
+        false,
+        source,
+        spinner,
+        #[cfg(feature = "gui")]
+        &None,
+        silent,
+    )
+    .and_then(|()| {
+        if shared && source.is_some() {
+            git::share_objects(destination_clone, destination_backup)?;
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) if errors.is_empty() => orphaned.map_or(Ok(()), Err),
+        Ok(()) => Err(anyhow!(errors.join(" "))),
+        Err(error) if errors.is_empty() => Err(error),
+        Err(error) => Err(anyhow!("{error} {}", errors.join(" "))),
+    }
+}
+
+fn rewrite_redirect(
+    config_path: &Path,
+    url: &str,
+    destination_clone: &mut String,
+    destination_backup: &mut String,
+    mirror: bool,
+) -> Result<()> {
+    let Some(location) = git::redirect(if mirror {
+        destination_backup
+    } else {
+        destination_clone
+    })?
+    else {
+        return Ok(());
+    };
+
+    let config = fs::read_to_string(config_path)?;
+    let mut in_targets = false;
+    let mut rewritten = config
+        .lines()
+        .map(|line| {
+            // Only entries under `targets:` are rewritten, push remotes keep their URLs
+            if !line.starts_with([' ', '-', '#']) && !line.is_empty() {
+                in_targets = line.trim_end() == "targets:";
+                return line.to_owned();
+            }
+
+            let value = line
+                .split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_start_matches("- ")
+                .trim_start_matches("url:")
+                .trim()
+                .trim_matches(['"', '\'']);
+
+            if in_targets && value == url {
+                line.replacen(url, &location, 1)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if config.ends_with('\n') {
+        rewritten.push('\n');
+    }
+
+    // Flow-style or multi-line entries are left alone, so report them instead
+    let mut expected: serde_yaml::Value = serde_yaml::from_str(&config)?;
+
+    if let Some(targets) = expected
+        .get_mut("targets")
+        .and_then(serde_yaml::Value::as_sequence_mut)
+    {
+        for target in targets {
+            let entry = match target {
+                serde_yaml::Value::Mapping(mapping) => mapping.get_mut("url"),
+                target => Some(target),
+            };
+
+            if let Some(entry) = entry.filter(|entry| entry.as_str() == Some(url)) {
+                *entry = serde_yaml::Value::from(location.as_str());
+            }
+        }
+    }
+
+    if serde_yaml::from_str::<serde_yaml::Value>(&rewritten)? != expected {
+        return Err(anyhow!(
+            "Redirected to {location}, update {url} in {}",
+            config_path.display()
+        ));
+    }
+
+    fs::write(config_path, rewritten)?;
+
+    let name = get_name(url);
+    let new_name = get_name(&location);
+    let mut destinations = vec![(destination_clone, String::new())];
+
+    if mirror {
+        destinations.push((destination_backup, String::from(".dorst")));
+    }
+
+    for (destination, suffix) in destinations {
+        git::apply_redirect(destination, &location)?;
+
+        // Renamed repositories move to their new directories unless these are taken
+        let parent = destination
+            .strip_suffix(&format!("{name}{suffix}"))
+            .unwrap_or_default();
+        let renamed = format!("{parent}{new_name}{suffix}");
+
+        if new_name != name && !Path::new(&renamed).exists() {
+            fs::rename(&*destination, &renamed)?;
+            *destination = renamed;
+        }
+    }
+
+    Ok(())
+}
+
+fn find_mirrors(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut mirrors: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|entry| entry.is_dir() && entry.extension().is_some_and(|ext| ext == "dorst"))
+        .collect();
+
+    if mirrors.is_empty() {
+        return Err(anyhow!("No backup mirrors in {}", path.display()));
+    }
+
+    mirrors.sort();
+
+    Ok(mirrors)
+}
+
+fn select_mirrors(path: &Path, names: Option<&[&String]>) -> Result<Vec<PathBuf>> {
+    let mirrors: Vec<PathBuf> = find_mirrors(path)?
+        .into_iter()
+        .filter(|mirror| {
+            let name = mirror.file_stem().unwrap().to_string_lossy();
+            names.is_none_or(|names| names.iter().any(|target| **target == name))
+        })
+        .collect();
+
+    if mirrors.is_empty() {
+        return Err(anyhow!("No matching backup mirrors in {}", path.display()));
+    }
+
+    Ok(mirrors)
+}
+
+fn verify(matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("path").unwrap();
+    let mirrors = find_mirrors(path)?;
+
+    println!("{BANNER}");
+
+    let mut err_count = 0;
+    let mut compl_count = 0;
+
+    for mirror in mirrors {
+        let name = mirror.file_stem().unwrap().to_string_lossy();
+
+        match git::verify_mirror(&mirror) {
+            Ok(()) => {
+                compl_count += 1;
+                println!("\x1b[1;96mok\x1b[0m \x1b[0;93m{name}\x1b[0m");
+            }
+            Err(error) => {
+                err_count += 1;
+                eprintln!("\x1b[1;31mError:\x1b[0m {name}: {error}");
+            }
+        }
+    }
+
+    if err_count > 0 {
+        eprintln!(
+            "\u{2517}\u{2578}\x1b[1mVERIFIED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m/\x1b[0m\x1b[1;91m{err_count}\x1b[0m\x1b[37m)\x1b[0m"
+        );
+
+        std::process::exit(1);
+    } else {
+        println!(
+            "\u{2517}\u{2578}\x1b[1mVERIFIED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m)\x1b[0m"
+        );
+    }
+
+    Ok(())
+}
+
+fn decrypt(matches: &ArgMatches) -> Result<()> {
+    let files = matches.get_many::<PathBuf>("files").unwrap();
+    let mut config = Config::default();
+
+    config.open_existing(matches)?;
+
+    let encryption = config
+        .settings
+        .encryption
+        .ok_or_else(|| anyhow!("No encryption settings"))?;
+
+    println!("{BANNER}");
+
+    for file in files {
+        if file.extension().is_none_or(|ext| ext != crypto::EXTENSION) {
+            return Err(anyhow!("{} is not an encrypted file", file.display()));
+        }
+
+        let output = file.with_extension("");
+
+        if output.exists() {
+            return Err(anyhow!("{} already exists", output.display()));
+        }
+
+        crypto::decrypt_file(file, &output, &encryption)?;
+        println!(
+            "\x1b[1;96mdecrypted\x1b[0m \x1b[0;93m{}\x1b[0m",
+            output.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn restore(matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("path").unwrap();
+    let names: Option<Vec<&String>> = matches.get_many("target").map(Iterator::collect);
+    let push = matches.get_one::<String>("push");
+    let mut config = Config::default();
+
+    config.open_existing(matches)?;
+
+    if config.source_directory.is_empty() {
+        return Err(anyhow!("No source directory configured"));
+    }
+
+    if let Some(bundles) = matches.get_one::<PathBuf>("bundles") {
+        import_bundles(
+            bundles,
+            path,
+            names.as_deref(),
+            config.settings.encryption.as_ref(),
         )?;
     }
 
+    let mirrors = select_mirrors(path, names.as_deref())?;
+
+    if push.is_some() && mirrors.len() > 1 {
+        return Err(anyhow!("--push requires a single target"));
+    }
+
+    println!("{BANNER}");
+
+    let mut err_count = 0;
+    let mut compl_count = 0;
+
+    for mirror in mirrors {
+        let name = mirror.file_stem().unwrap().to_string_lossy().into_owned();
+        let destination = format!(
+            "{}/{name}",
+            PathBuf::from(expand_path(&config.source_directory)).display()
+        );
+
+        match restore_target(&mirror, &name, &destination, &config, push) {
+            Ok(()) => {
+                compl_count += 1;
+                println!("\x1b[1;96mrestored\x1b[0m \x1b[0;93m{name}\x1b[0m");
+            }
+            Err(error) => {
+                err_count += 1;
+                eprintln!("\x1b[1;31mError:\x1b[0m {name}: {error}");
+            }
+        }
+    }
+
+    if err_count > 0 {
+        eprintln!(
+            "\u{2517}\u{2578}\x1b[1mRESTORED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m/\x1b[0m\x1b[1;91m{err_count}\x1b[0m\x1b[37m)\x1b[0m"
+        );
+
+        std::process::exit(1);
+    } else {
+        println!(
+            "\u{2517}\u{2578}\x1b[1mRESTORED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m)\x1b[0m"
+        );
+    }
+
+    Ok(())
+}
+
+fn export(matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("path").unwrap();
+    let output = matches.get_one::<PathBuf>("output").unwrap();
+    let incremental = matches.get_flag("incremental");
+    let names: Option<Vec<&String>> = matches.get_many("target").map(Iterator::collect);
+    let mirrors = select_mirrors(path, names.as_deref())?;
+    let mut config = Config::default();
+
+    config.open_existing(matches)?;
+
+    fs::create_dir_all(output)?;
+
+    let output = fs::canonicalize(output)?;
+
+    println!("{BANNER}");
+
+    let mut err_count = 0;
+    let mut compl_count = 0;
+
+    for mirror in mirrors {
+        let name = mirror.file_stem().unwrap().to_string_lossy();
+
+        match git::bundle_mirror(
+            &mirror,
+            &output,
+            incremental,
+            config.settings.encryption.as_ref(),
+        ) {
+            Ok(Some(size)) => {
+                compl_count += 1;
+                println!(
+                    "\x1b[1;96mexported\x1b[0m \x1b[0;93m{name}\x1b[0m \
+                     \x1b[37m({})\x1b[0m",
+                    HumanBytes(size)
+                );
+            }
+            Ok(None) => {
+                compl_count += 1;
+                println!("\x1b[1;96munchanged\x1b[0m \x1b[0;93m{name}\x1b[0m");
+            }
+            Err(error) => {
+                err_count += 1;
+                eprintln!("\x1b[1;31mError:\x1b[0m {name}: {error}");
+            }
+        }
+    }
+
+    if err_count > 0 {
+        eprintln!(
+            "\u{2517}\u{2578}\x1b[1mEXPORTED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m/\x1b[0m\x1b[1;91m{err_count}\x1b[0m\x1b[37m)\x1b[0m"
+        );
+
+        std::process::exit(1);
+    } else {
+        println!(
+            "\u{2517}\u{2578}\x1b[1mEXPORTED\x1b[0m \
+             \x1b[37m(\x1b[0m\x1b[1;92m{compl_count}\
+             \x1b[0m\x1b[37m)\x1b[0m"
+        );
+    }
+
+    Ok(())
+}
+
+fn import_bundles(
+    bundles: &Path,
+    path: &Path,
+    names: Option<&[&String]>,
+    encryption: Option<&Encryption>,
+) -> Result<()> {
+    let mut chains: Vec<PathBuf> = fs::read_dir(bundles)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|entry| entry.to_string_lossy().ends_with(".chain.yml"))
+        .collect();
+
+    chains.sort();
+    fs::create_dir_all(path)?;
+
+    for chain in chains {
+        let file_name = chain.file_name().unwrap().to_string_lossy();
+        let name = file_name.trim_end_matches(".chain.yml");
+        let mirror = path.join(format!("{name}.dorst"));
+
+        if mirror.exists() || names.is_some_and(|names| !names.iter().any(|target| *target == name))
+        {
+            continue;
+        }
+
+        git::unbundle_chain(&chain, &mirror, encryption)
+            .map_err(|error| anyhow!("{name}: {error}"))?;
+    }
+
     Ok(())
 }
 
+fn restore_target(
+    mirror: &Path,
+    name: &str,
+    destination: &str,
+    config: &Config,
+    push: Option<&String>,
+) -> Result<()> {
+    if let Some(push) = push {
+        let mut remote = Target::new(push.clone());
+
+        remote.resolve(&config.settings);
+        return git::push_mirror(mirror, &remote);
+    }
+
+    let repo = git2::Repository::open_bare(mirror)?;
+    let target = match config
+        .targets
+        .iter()
+        .find(|target| get_name(&target.url) == name)
+    {
+        Some(target) => target.clone(),
+        None => {
+            let url = repo
+                .find_remote("origin")?
+                .url()
+                .map(String::from)
+                .ok_or_else(|| anyhow!("No origin URL in {}", mirror.display()))?;
+            let mut target = Target::new(url);
+
+            target.resolve(&config.settings);
+            target
+        }
+    };
+
+    if Path::new(destination).exists() {
+        return Err(anyhow!("{destination} already exists"));
+    }
+
+    git::process_target(
+        destination,
+        &target,
+        false,
+        mirror.to_str(),
+        None,
+        #[cfg(feature = "gui")]
+        &None,
+        Some(true),
+    )
+}
+
+fn maintain(
+    destination_clone: &str,
+    destination_backup: &str,
+    maintenance: &Maintenance,
+    force: bool,
+    mirror: bool,
+) -> Result<Option<u64>> {
+    let interval = (!force).then_some(maintenance.interval);
+    let mut reclaimed = git::maintain_repo(destination_clone, &maintenance.prune, interval)?;
+
+    if mirror {
+        // Shared clones may still need objects that are unreachable in the mirror
+        let prune = if git::shares_objects(destination_clone, destination_backup)? {
+            "never"
+        } else {
+            &maintenance.prune
+        };
+
+        if let Some(bytes) = git::maintain_repo(destination_backup, prune, interval)? {
+            reclaimed = Some(reclaimed.unwrap_or_default() + bytes);
+        }
+    }
+
+    Ok(reclaimed)
+}
+
 pub fn start() {
     let args = args();
-    if let Err(error) = cli(&args) {
+    let result = match args.subcommand() {
+        Some(("verify", matches)) => verify(matches),
+        Some(("restore", matches)) => restore(matches),
+        Some(("export", matches)) => export(matches),
+        Some(("decrypt", matches)) => decrypt(matches),
+        _ => cli(&args),
+    };
+
+    if let Err(error) = result {
         eprintln!("\x1b[1;31mError:\x1b[0m {error}");
         std::process::exit(1);
     }
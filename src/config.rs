@@ -0,0 +1,681 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    process::Command,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+#[cfg(feature = "cli")]
+use crate::util::{civil_date, civil_days};
+use crate::util::{expand_path, get_host, get_name, secret_prompt};
+
+pub type Hosts = BTreeMap<String, HostOptions>;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Hosts::is_empty")]
+    pub hosts: Hosts,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<Maintenance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub push: Vec<Target>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rewrite_redirects: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<Archive>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshots: Option<Snapshots>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<Manifest>,
+    #[serde(flatten)]
+    pub defaults: HostOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Maintenance {
+    pub interval: u64,
+    pub prune: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshots {
+    pub daily: usize,
+    pub weekly: usize,
+    pub monthly: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Archive {
+    pub format: ArchiveFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TargetEntry", into = "TargetEntry")]
+pub struct Target {
+    pub url: String,
+    pub options: TargetOptions,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetOptions {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sparse: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub push: Vec<Target>,
+    #[serde(flatten)]
+    pub host: HostOptions,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ssh_keys: Vec<SshKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<Retry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Timeout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<Verify>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, Header>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connections: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_mismatch: Option<UrlPolicy>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Header {
+    Value(String),
+    Secret(Secret),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: u64,
+    pub max_delay: u64,
+    pub errors: Vec<RetryError>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeout {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stall: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Verify {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fingerprints: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_hosts: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_keys: Option<HostKeyPolicy>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyPolicy {
+    Strict,
+    AcceptNew,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlPolicy {
+    #[default]
+    Update,
+    Warn,
+    Fail,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryError {
+    Timeout,
+    Connection,
+    Server,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SshKey {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<Secret>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Encryption {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<PrivateKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<Secret>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrivateKey {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<Secret>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Secret {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub prompt: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum TargetEntry {
+    Url(String),
+    Full {
+        url: String,
+        #[serde(flatten)]
+        options: Box<TargetOptions>,
+    },
+}
+
+impl Target {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            options: TargetOptions::default(),
+        }
+    }
+
+    pub fn resolve(&mut self, settings: &Settings) {
+        let name = get_name(&self.url);
+        let remotes = settings.push.iter().map(|remote| Self {
+            url: remote.url.replace("{name}", name),
+            options: remote.options.clone(),
+        });
+
+        self.options.push.extend(remotes);
+        self.resolve_host(settings);
+
+        for remote in &mut self.options.push {
+            remote.resolve_host(settings);
+        }
+    }
+
+    fn resolve_host(&mut self, settings: &Settings) {
+        let host = get_host(&self.url);
+
+        if let Some(host) = host.and_then(|host| settings.hosts.get(host)) {
+            self.options.host.merge(host);
+        }
+
+        if self.options.host.proxy.is_none()
+            && host.is_some_and(|host| {
+                settings
+                    .no_proxy
+                    .iter()
+                    .any(|pattern| no_proxy_match(host, pattern))
+            })
+        {
+            self.options.host.proxy = Some(String::from("none"));
+        }
+
+        self.options.host.merge(&settings.defaults);
+    }
+}
+
+impl HostOptions {
+    fn merge(&mut self, other: &Self) {
+        if self.username.is_none() {
+            self.username.clone_from(&other.username);
+        }
+
+        if self.ssh_keys.is_empty() {
+            self.ssh_keys.clone_from(&other.ssh_keys);
+        }
+
+        if self.token.is_none() {
+            self.token.clone_from(&other.token);
+        }
+
+        if self.retry.is_none() {
+            self.retry.clone_from(&other.retry);
+        }
+
+        if self.timeout.is_none() {
+            self.timeout.clone_from(&other.timeout);
+        }
+
+        if self.proxy.is_none() {
+            self.proxy.clone_from(&other.proxy);
+        }
+
+        if self.verify.is_none() {
+            self.verify.clone_from(&other.verify);
+        }
+
+        if self.headers.is_empty() {
+            self.headers.clone_from(&other.headers);
+        }
+
+        if self.connections.is_none() {
+            self.connections = other.connections;
+        }
+
+        if self.url_mismatch.is_none() {
+            self.url_mismatch = other.url_mismatch;
+        }
+    }
+}
+
+impl Retry {
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .delay
+            .saturating_mul(2_u64.saturating_pow(attempt.saturating_sub(1)));
+
+        Duration::from_secs(delay.min(self.max_delay))
+    }
+}
+
+impl Header {
+    pub fn resolve(&self, label: &str) -> Result<String> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Secret(secret) => secret.resolve(label),
+        }
+    }
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self {
+            daily: 7,
+            weekly: 4,
+            monthly: 12,
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Snapshots {
+    // Keeps the newest generation of each of the latest days, weeks and months
+    pub fn retain<'a>(&self, generations: &[&'a str]) -> Vec<&'a str> {
+        let mut dated: Vec<(i64, &str)> = generations
+            .iter()
+            .filter_map(|generation| civil_days(generation).map(|days| (days, *generation)))
+            .collect();
+
+        dated.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut keep: Vec<&str> = Vec::new();
+        let period = |index: usize, days: i64| match index {
+            0 => days,
+            // Weeks start on Monday (1970-01-01 was a Thursday)
+            1 => (days + 3).div_euclid(7),
+            _ => {
+                let (year, month, _) = civil_date(days);
+                year * 12 + i64::from(month)
+            }
+        };
+
+        for (index, count) in [self.daily, self.weekly, self.monthly]
+            .into_iter()
+            .enumerate()
+        {
+            let mut seen = Vec::new();
+
+            for (days, generation) in &dated {
+                if seen.len() == count {
+                    break;
+                }
+
+                if !seen.contains(&period(index, *days)) {
+                    seen.push(period(index, *days));
+
+                    if !keep.contains(generation) {
+                        keep.push(generation);
+                    }
+                }
+            }
+        }
+
+        keep
+    }
+}
+
+impl ArchiveFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            interval: 7,
+            prune: String::from("2.weeks.ago"),
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            delay: 2,
+            max_delay: 60,
+            errors: vec![
+                RetryError::Timeout,
+                RetryError::Connection,
+                RetryError::Server,
+            ],
+        }
+    }
+}
+
+impl Secret {
+    pub fn resolve(&self, label: &str) -> Result<String> {
+        static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+        if let Some(var) = &self.env {
+            if let Ok(value) = env::var(var) {
+                return Ok(value);
+            }
+        }
+
+        if let Some(file) = &self.file {
+            if let Ok(value) = fs::read_to_string(expand_path(file)) {
+                return Ok(value.trim_end_matches(['\r', '\n']).to_owned());
+            }
+        }
+
+        if let Some(command) = &self.command {
+            let output = Command::new("sh").arg("-c").arg(command).output()?;
+
+            if output.status.success() {
+                let value = String::from_utf8_lossy(&output.stdout);
+                return Ok(value.trim_end_matches(['\r', '\n']).to_owned());
+            }
+        }
+
+        if self.prompt {
+            let mut cache = CACHE.get_or_init(Mutex::default).lock().unwrap();
+
+            if let Some(value) = cache.get(label) {
+                return Ok(value.clone());
+            }
+
+            let value = secret_prompt(&format!("{label}: "))?;
+            cache.insert(label.to_owned(), value.clone());
+
+            return Ok(value);
+        }
+
+        Err(anyhow!("No secret available for {label}"))
+    }
+}
+
+fn no_proxy_match(host: &str, pattern: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let pattern = pattern.trim_start_matches("*.").trim_start_matches('.');
+
+    pattern == "*"
+        || host.eq_ignore_ascii_case(pattern)
+        || host
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", pattern.to_ascii_lowercase()))
+}
+
+impl From<TargetEntry> for Target {
+    fn from(entry: TargetEntry) -> Self {
+        match entry {
+            TargetEntry::Url(url) => Self::new(url),
+            TargetEntry::Full { url, options } => Self {
+                url,
+                options: *options,
+            },
+        }
+    }
+}
+
+impl From<Target> for TargetEntry {
+    fn from(target: Target) -> Self {
+        if target.options == TargetOptions::default() {
+            Self::Url(target.url)
+        } else {
+            Self::Full {
+                url: target.url,
+                options: Box::new(target.options),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_resolve() {
+    let settings: Settings = serde_yaml::from_str(
+        "username: foo\nretry:\n  attempts: 3\nhosts:\n  example.com:\n    username: git\n\
+         \x20   ssh_keys:\n      - path: ~/.ssh/id_ed25519\n",
+    )
+    .unwrap();
+
+    let mut target = Target::new(String::from("ssh://example.com/foo/bar"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.username == Some(String::from("git")));
+    assert!(target.options.host.ssh_keys[0].path == "~/.ssh/id_ed25519");
+    assert!(target.options.host.retry.unwrap().attempts == 3);
+
+    let mut target = Target::new(String::from("https://example.org/foo/bar"));
+    target.options.host.username = Some(String::from("bar"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.username == Some(String::from("bar")));
+    assert!(target.options.host.ssh_keys.is_empty());
+
+    let mut target = Target::new(String::from("/tmp/foo"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.username == Some(String::from("foo")));
+}
+
+#[test]
+fn test_proxy() {
+    let settings: Settings = serde_yaml::from_str(
+        "proxy: http://proxy:3128\nno_proxy:\n  - .internal\nhosts:\n  gitlab.internal:\n\
+         \x20   proxy: http://gateway:8080\n",
+    )
+    .unwrap();
+
+    let mut target = Target::new(String::from("https://github.com/foo/bar"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.proxy == Some(String::from("http://proxy:3128")));
+
+    let mut target = Target::new(String::from("https://git.internal/foo/bar"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.proxy == Some(String::from("none")));
+
+    let mut target = Target::new(String::from("https://gitlab.internal/foo/bar"));
+    target.resolve(&settings);
+
+    assert!(target.options.host.proxy == Some(String::from("http://gateway:8080")));
+    assert!(!no_proxy_match("notinternal", ".internal"));
+}
+
+#[test]
+fn test_backoff() {
+    let retry = Retry {
+        delay: 2,
+        max_delay: 10,
+        ..Retry::default()
+    };
+
+    assert!(retry.backoff(1) == Duration::from_secs(2));
+    assert!(retry.backoff(2) == Duration::from_secs(4));
+    assert!(retry.backoff(4) == Duration::from_secs(10));
+}
+
+#[test]
+fn test_secret() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+
+    std::io::Write::write_all(&mut file, b"foo\n").unwrap();
+
+    let secret = Secret {
+        env: Some(String::from("DORST_TEST_SECRET_UNSET")),
+        file: Some(file.path().display().to_string()),
+        command: None,
+        prompt: false,
+    };
+
+    assert!(secret.resolve("test").unwrap() == "foo");
+
+    let secret = Secret {
+        command: Some(String::from("echo bar")),
+        ..Secret::default()
+    };
+
+    assert!(secret.resolve("test").unwrap() == "bar");
+    assert!(Secret::default().resolve("test").is_err());
+}
+
+#[test]
+fn test_header() {
+    let host: HostOptions =
+        serde_yaml::from_str("headers:\n  X-Foo: bar\n  X-Baz:\n    command: echo qux\n").unwrap();
+
+    assert!(host.headers["X-Foo"].resolve("test").unwrap() == "bar");
+    assert!(host.headers["X-Baz"].resolve("test").unwrap() == "qux");
+}
+
+#[test]
+fn test_target() {
+    let targets: Vec<Target> =
+        serde_yaml::from_str("- foo/bar\n- url: foo/baz\n  sparse:\n    - src\n").unwrap();
+
+    assert!(targets[0] == Target::new(String::from("foo/bar")));
+    assert!(targets[1].url == "foo/baz");
+    assert!(targets[1].options.sparse == vec![String::from("src")]);
+
+    let yaml = serde_yaml::to_string(&targets).unwrap();
+
+    assert!(yaml.starts_with("- foo/bar\n- url: foo/baz\n"));
+}
+
+#[test]
+fn test_push() {
+    let settings: Settings = serde_yaml::from_str(
+        "push:\n  - /mnt/nas/{name}.git\nhosts:\n  git.example.com:\n    username: git\n",
+    )
+    .unwrap();
+
+    let mut target: Target = serde_yaml::from_str(
+        "url: https://github.com/foo/bar\npush:\n  - ssh://git.example.com/foo/bar.git\n",
+    )
+    .unwrap();
+
+    target.resolve(&settings);
+
+    assert!(target.options.push.len() == 2);
+    assert!(target.options.push[0].options.host.username == Some(String::from("git")));
+    assert!(target.options.push[1].url == "/mnt/nas/bar.git");
+}
+
+#[test]
+fn test_archive() {
+    let settings: Settings = serde_yaml::from_str("archive:\n  format: zip\n").unwrap();
+    let archive = settings.archive.unwrap();
+
+    assert!(archive.format == ArchiveFormat::Zip);
+    assert!(archive.directory.is_none());
+    assert!(ArchiveFormat::default().extension() == "tar.gz");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_snapshots() {
+    let snapshots = Snapshots {
+        daily: 2,
+        weekly: 2,
+        monthly: 2,
+    };
+
+    let generations = [
+        "2024-05-01",
+        "2024-05-14",
+        "2024-05-20",
+        "2024-05-21",
+        "2024-05-22",
+        "2024-04-30",
+        "2024-03-01",
+        "latest",
+    ];
+
+    let mut keep = snapshots.retain(&generations);
+
+    keep.sort_unstable();
+
+    assert!(keep == ["2024-04-30", "2024-05-14", "2024-05-21", "2024-05-22"]);
+}
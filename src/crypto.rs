@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+use openssl::{
+    encrypt::{Decrypter, Encrypter},
+    hash::MessageDigest,
+    pkcs5::pbkdf2_hmac,
+    pkey::PKey,
+    rand::rand_bytes,
+    rsa::Padding,
+    symm::{Cipher, Crypter, Mode},
+};
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use crate::{config::Encryption, util::expand_path};
+
+pub const EXTENSION: &str = "enc";
+
+const MAGIC: &[u8; 6] = b"DORST\x01";
+const PASSPHRASE: u8 = 0;
+const PUBLIC_KEY: u8 = 1;
+const ITERATIONS: u32 = 600_000;
+// The header is only authenticated after the key is derived
+const ACCEPTED_ITERATIONS: RangeInclusive<u32> = 100_000..=10_000_000;
+const TAG_LEN: usize = 16;
+const CHUNK: usize = 65536;
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<[u8; 32]> {
+    let mut key = [0; 32];
+
+    pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        iterations.try_into()?,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+
+    Ok(key)
+}
+
+// Header: magic, mode, key material (wrapped key or KDF parameters), IV.
+// The header is authenticated along with the AES-256-GCM ciphertext.
+pub fn encrypt_file(input: &Path, output: &Path, encryption: &Encryption) -> Result<()> {
+    let mut header = MAGIC.to_vec();
+    let mut key = [0; 32];
+
+    if let Some(public_key) = &encryption.public_key {
+        let pkey = PKey::public_key_from_pem(&fs::read(expand_path(public_key))?)?;
+        let mut encrypter = Encrypter::new(&pkey)?;
+
+        rand_bytes(&mut key)?;
+        encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+
+        let mut wrapped = vec![0; encrypter.encrypt_len(&key)?];
+        let len = encrypter.encrypt(&key, &mut wrapped)?;
+
+        header.push(PUBLIC_KEY);
+        header.extend(u16::try_from(len)?.to_be_bytes());
+        header.extend(&wrapped[..len]);
+    } else if let Some(passphrase) = &encryption.passphrase {
+        let mut salt = [0; 16];
+
+        rand_bytes(&mut salt)?;
+        key = derive_key(&passphrase.resolve("backup encryption")?, &salt, ITERATIONS)?;
+        header.push(PASSPHRASE);
+        header.extend(ITERATIONS.to_be_bytes());
+        header.extend(salt);
+    } else {
+        return Err(anyhow!("Encryption requires a public key or a passphrase"));
+    }
+
+    let mut iv = [0; 12];
+
+    rand_bytes(&mut iv)?;
+    header.extend(iv);
+
+    let mut crypter = Crypter::new(Cipher::aes_256_gcm(), Mode::Encrypt, &key, Some(&iv))?;
+    let mut reader = File::open(input)?;
+    let partial = output.with_extension("part");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    let mut buffer = vec![0; CHUNK];
+    let mut block = vec![0; CHUNK + 16];
+
+    crypter.aad_update(&header)?;
+    writer.write_all(&header)?;
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+
+        if read == 0 {
+            break;
+        }
+
+        let len = crypter.update(&buffer[..read], &mut block)?;
+
+        writer.write_all(&block[..len])?;
+    }
+
+    let len = crypter.finalize(&mut block)?;
+    let mut tag = [0; TAG_LEN];
+
+    crypter.get_tag(&mut tag)?;
+    writer.write_all(&block[..len])?;
+    writer.write_all(&tag)?;
+    writer.into_inner().map_err(|error| error.into_error())?;
+    fs::rename(partial, output)?;
+
+    Ok(())
+}
+
+pub fn decrypt_file(input: &Path, output: &Path, encryption: &Encryption) -> Result<()> {
+    let mut reader = File::open(input)?;
+    let mut header = [0; 7];
+
+    reader.read_exact(&mut header)?;
+
+    if header[..MAGIC.len()] != MAGIC[..] {
+        return Err(anyhow!("{} is not an encrypted backup", input.display()));
+    }
+
+    let mut header = header.to_vec();
+    let key = match header[MAGIC.len()] {
+        PUBLIC_KEY => {
+            let private_key = encryption
+                .private_key
+                .as_ref()
+                .ok_or_else(|| anyhow!("Decryption requires a private key"))?;
+            let pem = fs::read(expand_path(&private_key.path))?;
+            let pkey = match &private_key.passphrase {
+                Some(passphrase) => PKey::private_key_from_pem_passphrase(
+                    &pem,
+                    passphrase.resolve(&private_key.path)?.as_bytes(),
+                )?,
+                None => PKey::private_key_from_pem(&pem)?,
+            };
+
+            let mut len = [0; 2];
+
+            reader.read_exact(&mut len)?;
+
+            let mut wrapped = vec![0; u16::from_be_bytes(len).into()];
+
+            reader.read_exact(&mut wrapped)?;
+
+            let mut decrypter = Decrypter::new(&pkey)?;
+
+            decrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+
+            let mut key = vec![0; decrypter.decrypt_len(&wrapped)?];
+            let key_len = decrypter.decrypt(&wrapped, &mut key)?;
+
+            header.extend(len);
+            header.extend(&wrapped);
+            key[..key_len].try_into()?
+        }
+        PASSPHRASE => {
+            let passphrase = encryption
+                .passphrase
+                .as_ref()
+                .ok_or_else(|| anyhow!("Decryption requires a passphrase"))?;
+            let mut params = [0; 20];
+
+            reader.read_exact(&mut params)?;
+            header.extend(params);
+
+            let iterations = u32::from_be_bytes(params[..4].try_into()?);
+
+            if !ACCEPTED_ITERATIONS.contains(&iterations) {
+                return Err(anyhow!("{} has invalid KDF parameters", input.display()));
+            }
+
+            derive_key(
+                &passphrase.resolve("backup encryption")?,
+                &params[4..],
+                iterations,
+            )?
+        }
+        _ => return Err(anyhow!("Unsupported encryption mode")),
+    };
+
+    let mut iv = [0; 12];
+
+    reader.read_exact(&mut iv)?;
+    header.extend(iv);
+
+    let mut crypter = Crypter::new(Cipher::aes_256_gcm(), Mode::Decrypt, &key, Some(&iv))?;
+    let mut remaining = fs::metadata(input)?
+        .len()
+        .checked_sub((header.len() + TAG_LEN).try_into()?)
+        .ok_or_else(|| anyhow!("{} is truncated", input.display()))?;
+    let partial = output.with_extension("part");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    let mut buffer = vec![0; CHUNK];
+    let mut block = vec![0; CHUNK + 16];
+
+    crypter.aad_update(&header)?;
+
+    let result = (|| -> Result<()> {
+        while remaining > 0 {
+            let size = usize::try_from(remaining)?.min(CHUNK);
+
+            reader.read_exact(&mut buffer[..size])?;
+            remaining -= u64::try_from(size)?;
+
+            let len = crypter.update(&buffer[..size], &mut block)?;
+
+            writer.write_all(&block[..len])?;
+        }
+
+        let mut tag = [0; TAG_LEN];
+
+        reader.read_exact(&mut tag)?;
+        crypter.set_tag(&tag)?;
+
+        let len = crypter
+            .finalize(&mut block)
+            .map_err(|_| anyhow!("Decryption failed (wrong key or corrupted file)"))?;
+
+        writer.write_all(&block[..len])?;
+        writer.flush()?;
+
+        Ok(())
+    })();
+
+    drop(writer);
+
+    if result.is_err() {
+        fs::remove_file(&partial).ok();
+        return result;
+    }
+
+    fs::rename(partial, output)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_roundtrip() {
+    use crate::config::Secret;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let secret = dir.path().join("passphrase");
+    let plain = dir.path().join("dorst-crypto.txt");
+    let sealed = dir.path().join("dorst-crypto.txt.enc");
+    let opened = dir.path().join("dorst-crypto.out");
+    let data = vec![7; CHUNK * 2 + 5];
+
+    fs::write(&secret, "foo\n").unwrap();
+    fs::write(&plain, &data).unwrap();
+
+    let encryption = Encryption {
+        passphrase: Some(Secret {
+            file: Some(secret.display().to_string()),
+            ..Secret::default()
+        }),
+        ..Encryption::default()
+    };
+
+    encrypt_file(&plain, &sealed, &encryption).unwrap();
+    decrypt_file(&sealed, &opened, &encryption).unwrap();
+
+    assert!(fs::read(&opened).unwrap() == data);
+
+    let original = fs::read(&sealed).unwrap();
+    let mut corrupted = original.clone();
+    let last = corrupted.len() - 1;
+
+    corrupted[last] ^= 1;
+    fs::write(&sealed, corrupted).unwrap();
+
+    assert!(decrypt_file(&sealed, &opened, &encryption).is_err());
+
+    let mut corrupted = original;
+    let params = MAGIC.len() + 1;
+
+    corrupted[params..params + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    fs::write(&sealed, corrupted).unwrap();
+
+    assert!(decrypt_file(&sealed, &opened, &encryption).is_err());
+}
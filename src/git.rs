@@ -1,84 +1,1257 @@
+use anyhow::anyhow;
 use anyhow::Result;
 #[cfg(feature = "gui")]
 use async_channel::Sender;
-use git2::{AutotagOption, Cred, FetchOptions, RemoteCallbacks, Repository};
+use git2::{
+    cert::{Cert, CertHostkey},
+    AutotagOption, CertificateCheckStatus, Cred, Direction, ErrorClass, FetchOptions, ProxyOptions,
+    PushOptions, Remote, RemoteCallbacks, Repository, TraceLevel,
+};
 #[cfg(feature = "cli")]
 use indicatif::{HumanBytes, ProgressBar};
+use openssl::{
+    base64::{decode_block, encode_block},
+    hash::MessageDigest,
+    pkey::PKey,
+    sha::sha256,
+    sign::Signer,
+    stack::Stack,
+    x509::{store::X509StoreBuilder, verify::X509VerifyParam, X509StoreContext, X509},
+};
 
+#[cfg(feature = "cli")]
+use crate::config::{ArchiveFormat, Encryption};
+use crate::config::{
+    HostKeyPolicy, HostOptions, RetryError, SshKey, Target, Timeout, UrlPolicy, Verify,
+};
+#[cfg(feature = "cli")]
+use crate::crypto;
 #[cfg(feature = "gui")]
 use crate::gui::window::{RowMessage, Status};
+use crate::limit::{self, HostSlot};
+#[cfg(feature = "cli")]
+use crate::util::{dir_size, get_name};
+use crate::util::{expand_path, get_host, get_port};
+
+#[cfg(feature = "cli")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "cli")]
+use std::io;
+use std::{
+    cell::{Cell, RefCell},
+    env, fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Once,
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "cli")]
+use std::{
+    collections::BTreeMap,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug)]
+pub struct RetryFailure {
+    pub attempts: u32,
+    pub error: git2::Error,
+}
+
+impl fmt::Display for RetryFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (failed after {} attempts)",
+            self.error, self.attempts
+        )
+    }
+}
+
+impl std::error::Error for RetryFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+static TRACE: Once = Once::new();
+
+thread_local! {
+    static LAST_REQUEST: RefCell<Option<String>> = const { RefCell::new(None) };
+    static RECEIVED: Cell<u64> = const { Cell::new(0) };
+}
+
+#[derive(Debug)]
+pub struct Orphaned {
+    pub error: git2::Error,
+}
+
+impl fmt::Display for Orphaned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (backup kept)", self.error)
+    }
+}
+
+impl std::error::Error for Orphaned {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+struct Watchdog {
+    started: Instant,
+    progress: Cell<(Instant, usize)>,
+    received: Cell<Option<usize>>,
+    stall: Option<Duration>,
+    total: Option<Duration>,
+    expired: Cell<Option<String>>,
+}
+
+impl Watchdog {
+    fn new(timeout: Option<&Timeout>, throttle: bool) -> Self {
+        let now = Instant::now();
+
+        Self {
+            started: now,
+            progress: Cell::new((now, 0)),
+            received: Cell::new(throttle.then_some(0)),
+            stall: timeout.and_then(|timeout| timeout.stall.map(Duration::from_secs)),
+            total: timeout.and_then(|timeout| timeout.total.map(Duration::from_secs)),
+            expired: Cell::new(None),
+        }
+    }
+
+    fn check(&self, stats: &git2::Progress) -> bool {
+        let current = stats.received_bytes() + stats.indexed_objects() + stats.indexed_deltas();
+        let (_, previous) = self.progress.get();
+
+        if let Some(received) = self.received.get() {
+            limit::throttle(stats.received_bytes().saturating_sub(received));
+            self.received.set(Some(stats.received_bytes()));
+        }
+
+        if current != previous {
+            self.progress.set((Instant::now(), current));
+        }
+
+        self.expire()
+    }
+
+    // Sideband messages show the server is still preparing the pack
+    fn sideband(&self) -> bool {
+        let (_, current) = self.progress.get();
+
+        self.progress.set((Instant::now(), current));
+        self.expire()
+    }
+
+    fn expire(&self) -> bool {
+        let now = Instant::now();
+        let (last, _) = self.progress.get();
+
+        if let Some(stall) = self.stall.filter(|stall| now - last > *stall) {
+            self.expired.set(Some(format!(
+                "transfer timed out (no progress for {}s)",
+                stall.as_secs()
+            )));
+
+            return false;
+        }
+
+        if let Some(total) = self.total.filter(|total| now - self.started > *total) {
+            self.expired.set(Some(format!(
+                "transfer timed out (exceeded {}s)",
+                total.as_secs()
+            )));
+
+            return false;
+        }
+
+        true
+    }
+
+    fn error(&self, error: git2::Error) -> git2::Error {
+        match self.expired.take() {
+            Some(message) => {
+                git2::Error::new(git2::ErrorCode::GenericError, ErrorClass::Net, message)
+            }
+            None => error,
+        }
+    }
+}
+
+// Network transfers (the throttled ones) add up to the bytes received by this thread
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if let Some(received) = self.received.get() {
+            RECEIVED.with(|total| total.set(total.get() + received as u64));
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn take_received() -> u64 {
+    RECEIVED.with(Cell::take)
+}
+
+pub fn set_callbacks<'a>(
+    git_config: &'a git2::Config,
+    host: &'a HostOptions,
+    url: &str,
+) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let port = get_port(url);
+    let mut ssh_attempts = 0;
+    let mut http_attempts = 0;
+
+    callbacks.credentials(move |url, username_from_url, allowed_types| {
+        let username = username_from_url.or(host.username.as_deref());
+
+        if allowed_types.is_user_pass_plaintext() {
+            http_attempts += 1;
+
+            match &host.token {
+                Some(token) if http_attempts == 1 => {
+                    let label = format!("Token for {}", get_host(url).unwrap_or(url));
+                    let token = token
+                        .resolve(&label)
+                        .map_err(|error| git2::Error::from_str(&error.to_string()))?;
+
+                    Cred::userpass_plaintext(username.unwrap_or("oauth2"), &token)
+                }
+                _ => Cred::credential_helper(git_config, url, username_from_url),
+            }
+        } else if allowed_types.is_username() {
+            match username {
+                Some(username) => Cred::username(username),
+                None => Err(git2::Error::from_str("Could not extract username from URL")),
+            }
+        } else if allowed_types.is_ssh_key() {
+            match username {
+                Some(username) => {
+                    ssh_attempts += 1;
+                    ssh_credentials(username, host, ssh_attempts)
+                }
+                None => Err(git2::Error::from_str("Could not extract username from URL")),
+            }
+        } else {
+            Cred::default()
+        }
+    });
+
+    if let Some(verify) = &host.verify {
+        callbacks.certificate_check(move |cert, hostname| {
+            check_certificate(verify, cert, hostname, port).map_err(|error| {
+                git2::Error::new(
+                    git2::ErrorCode::Certificate,
+                    ErrorClass::Callback,
+                    error.to_string(),
+                )
+            })
+        });
+    }
+
+    callbacks
+}
+
+fn check_certificate(
+    verify: &Verify,
+    cert: &Cert<'_>,
+    hostname: &str,
+    port: Option<u16>,
+) -> Result<CertificateCheckStatus> {
+    if let Some(x509) = cert.as_x509() {
+        if !verify.fingerprints.is_empty() {
+            let digest = sha256(x509.data());
+
+            return match_fingerprint(&verify.fingerprints, &digest, hostname);
+        }
+
+        if let Some(ca_bundle) = &verify.ca_bundle {
+            verify_chain(ca_bundle, x509.data(), hostname)?;
+
+            return Ok(CertificateCheckStatus::CertificateOk);
+        }
+    } else if let Some(hostkey) = cert.as_hostkey() {
+        if !verify.fingerprints.is_empty() {
+            let digest = hostkey
+                .hash_sha256()
+                .ok_or_else(|| anyhow!("No SHA-256 host key hash for {hostname}"))?;
+
+            return match_fingerprint(&verify.fingerprints, digest, hostname);
+        }
+
+        if verify.known_hosts.is_some() || verify.host_keys.is_some() {
+            check_known_hosts(verify, hostkey, hostname, port)?;
+
+            return Ok(CertificateCheckStatus::CertificateOk);
+        }
+    }
+
+    Ok(CertificateCheckStatus::CertificatePassthrough)
+}
+
+fn match_fingerprint(
+    fingerprints: &[String],
+    digest: &[u8],
+    hostname: &str,
+) -> Result<CertificateCheckStatus> {
+    if fingerprints
+        .iter()
+        .filter_map(|fingerprint| parse_fingerprint(fingerprint))
+        .any(|fingerprint| fingerprint == digest)
+    {
+        Ok(CertificateCheckStatus::CertificateOk)
+    } else {
+        Err(anyhow!(
+            "Fingerprint mismatch for {hostname} (got SHA256:{})",
+            encode_block(digest).trim_end_matches('=')
+        ))
+    }
+}
+
+fn parse_fingerprint(fingerprint: &str) -> Option<Vec<u8>> {
+    let value = fingerprint
+        .split_once(':')
+        .filter(|(prefix, _)| prefix.eq_ignore_ascii_case("sha256"))
+        .map_or(fingerprint, |(_, value)| value);
+    let hex = value.replace(':', "");
+
+    if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
+    } else {
+        let padding = "=".repeat((4 - value.len() % 4) % 4);
+        decode_block(&format!("{value}{padding}")).ok()
+    }
+}
+
+fn verify_chain(ca_bundle: &str, data: &[u8], hostname: &str) -> Result<()> {
+    let mut store = X509StoreBuilder::new()?;
+    let mut param = X509VerifyParam::new()?;
+
+    for ca in X509::stack_from_pem(&fs::read(expand_path(ca_bundle))?)? {
+        store.add_cert(ca)?;
+    }
+
+    match hostname.parse() {
+        Ok(ip) => param.set_ip(ip)?,
+        Err(_) => param.set_host(hostname)?,
+    }
+
+    store.set_param(&param)?;
+
+    let store = store.build();
+    let cert = X509::from_der(data)?;
+    let chain = Stack::new()?;
+    let mut context = X509StoreContext::new()?;
+    let (valid, result) = context.init(&store, &cert, &chain, |context| {
+        Ok((context.verify_cert()?, context.error()))
+    })?;
+
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Certificate for {hostname} is not trusted by {ca_bundle}: {}",
+            result.error_string()
+        ))
+    }
+}
+
+fn check_known_hosts(
+    verify: &Verify,
+    hostkey: &CertHostkey<'_>,
+    hostname: &str,
+    port: Option<u16>,
+) -> Result<()> {
+    let hostname = &known_host_name(hostname, port);
+    let path = PathBuf::from(expand_path(
+        verify
+            .known_hosts
+            .as_deref()
+            .unwrap_or("~/.ssh/known_hosts"),
+    ));
+    let key_type = hostkey
+        .hostkey_type()
+        .map(|key_type| key_type.name())
+        .ok_or_else(|| anyhow!("Unknown host key type for {hostname}"))?;
+    let key = hostkey
+        .hostkey()
+        .map(encode_block)
+        .ok_or_else(|| anyhow!("No host key for {hostname}"))?;
+    let known_hosts = fs::read_to_string(&path).unwrap_or_default();
+    let mut changed = false;
+
+    for line in known_hosts.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with('@') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let (Some(hosts), Some(entry_type), Some(entry_key)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+
+        if entry_type != key_type
+            || !hosts
+                .split(',')
+                .any(|pattern| known_host_match(pattern, hostname))
+        {
+            continue;
+        }
+
+        if entry_key == key {
+            return Ok(());
+        }
+
+        changed = true;
+    }
+
+    if changed {
+        Err(anyhow!(
+            "Host key for {hostname} does not match {}",
+            path.display()
+        ))
+    } else if verify.host_keys == Some(HostKeyPolicy::AcceptNew) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+
+        writeln!(file, "{hostname} {key_type} {key}")?;
+
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Host key for {hostname} is not in {}",
+            path.display()
+        ))
+    }
+}
+
+// Like OpenSSH, keys for non-default ports are stored as `[host]:port`
+fn known_host_name(hostname: &str, port: Option<u16>) -> String {
+    match port {
+        Some(port) if port != 22 => format!("[{hostname}]:{port}"),
+        _ => hostname.to_owned(),
+    }
+}
+
+fn known_host_match(pattern: &str, hostname: &str) -> bool {
+    if let Some(hashed) = pattern.strip_prefix("|1|") {
+        let Some((salt, hash)) = hashed.split_once('|') else {
+            return false;
+        };
+
+        let digest = decode_block(salt).and_then(|salt| {
+            let key = PKey::hmac(&salt)?;
+            let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+
+            signer.update(hostname.as_bytes())?;
+            signer.sign_to_vec()
+        });
+
+        return digest.is_ok_and(|digest| encode_block(&digest) == hash);
+    }
+
+    pattern.eq_ignore_ascii_case(hostname)
+}
+
+fn ssh_credentials(
+    username: &str,
+    host: &HostOptions,
+    attempt: usize,
+) -> Result<Cred, git2::Error> {
+    let agent = host.ssh_keys.is_empty() || env::var_os("SSH_AUTH_SOCK").is_some();
+    let mut methods: Vec<Option<&SshKey>> = Vec::new();
+
+    if agent {
+        methods.push(None);
+    }
+
+    methods.extend(host.ssh_keys.iter().map(Some));
+
+    match methods.get(attempt - 1) {
+        Some(None) => Cred::ssh_key_from_agent(username),
+        Some(Some(key)) => {
+            let private_key = PathBuf::from(expand_path(&key.path));
+            let public_key = PathBuf::from(format!("{}.pub", private_key.display()));
+            let passphrase = key
+                .passphrase
+                .as_ref()
+                .map(|passphrase| passphrase.resolve(&format!("Passphrase for {}", key.path)))
+                .transpose()
+                .map_err(|error| git2::Error::from_str(&error.to_string()))?;
+
+            Cred::ssh_key(
+                username,
+                public_key.exists().then_some(public_key.as_path()),
+                &private_key,
+                passphrase.as_deref(),
+            )
+        }
+        None => Err(git2::Error::from_str(
+            "SSH authentication failed (no more keys to try)",
+        )),
+    }
+}
+
+fn set_headers(host: &HostOptions, url: &str) -> Result<Vec<String>, git2::Error> {
+    host.headers
+        .iter()
+        .map(|(name, value)| {
+            let label = format!("Header {name} for {}", get_host(url).unwrap_or(url));
+            let value = value
+                .resolve(&label)
+                .map_err(|error| git2::Error::from_str(&error.to_string()))?;
+
+            if name.contains([':', '\r', '\n', '\0']) || value.contains(['\r', '\n', '\0']) {
+                return Err(git2::Error::from_str(&format!(
+                    "Invalid HTTP header {name}"
+                )));
+            }
+
+            Ok(format!("{name}: {value}"))
+        })
+        .collect()
+}
+
+fn set_proxy(host: &HostOptions) -> Result<ProxyOptions<'_>, git2::Error> {
+    let mut proxy_options = ProxyOptions::new();
+
+    match host.proxy.as_deref() {
+        None => {
+            proxy_options.auto();
+        }
+        Some("none") => {}
+        Some(proxy) if proxy.starts_with("socks") => {
+            return Err(git2::Error::from_str(&format!(
+                "Unsupported proxy {proxy} (SOCKS is not available in libgit2)"
+            )));
+        }
+        Some(proxy) => {
+            proxy_options.url(proxy);
+        }
+    }
+
+    Ok(proxy_options)
+}
+
+pub fn set_default_branch(mirror: &Repository) -> Result<(), git2::Error> {
+    let remote = mirror.find_remote("origin")?;
+    let remote_branch = remote.name().unwrap();
+    let remote_branch_ref = mirror.resolve_reference_from_short_name(remote_branch)?;
+    let remote_branch_name = remote_branch_ref
+        .name()
+        .ok_or_else(|| git2::Error::from_str("No default branch"));
+
+    let branch = remote_branch_name?.to_owned();
+
+    mirror.set_head(&branch)?;
+
+    Ok(())
+}
+
+fn track_default_branch(
+    repo: &Repository,
+    branch: &str,
+    mirror: bool,
+) -> Result<Option<String>, git2::Error> {
+    if mirror {
+        let head = repo.find_reference("HEAD")?;
+
+        if head.symbolic_target() != Some(branch) && repo.find_reference(branch).is_ok() {
+            repo.set_head(branch)?;
+        }
+
+        return Ok(None);
+    }
+
+    let default_branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+    let tracking = format!("refs/remotes/origin/{default_branch}");
+
+    if repo.find_reference(&tracking).is_err() {
+        return Ok(None);
+    }
+
+    let origin_head = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .ok()
+        .and_then(|head| head.symbolic_target().map(String::from));
+    let mut config = repo.config()?;
+
+    if origin_head.as_ref() != Some(&tracking) {
+        // archive_tree resolves origin/HEAD, so it always follows upstream
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            &tracking,
+            true,
+            "default branch",
+        )?;
+
+        if let Some(old_branch) = origin_head
+            .as_deref()
+            .and_then(|head| head.strip_prefix("refs/remotes/origin/"))
+        {
+            config.set_str("dorst.defaultBranch", old_branch)?;
+        }
+    }
+
+    let Ok(old_branch) = config.get_string("dorst.defaultBranch") else {
+        return Ok(None);
+    };
+
+    // Keep flagging the clone until it leaves the old default
+    if old_branch != default_branch
+        && repo
+            .head()
+            .is_ok_and(|head| head.is_branch() && head.shorthand() == Some(&old_branch))
+    {
+        return Ok(Some(default_branch.to_owned()));
+    }
+
+    config.remove("dorst.defaultBranch")?;
+
+    Ok(None)
+}
+
+pub fn current_branch(destination: std::path::PathBuf) -> Result<String, git2::Error> {
+    let repo = Repository::open(destination)?;
+    let head = repo.head()?;
+
+    if head.is_branch() {
+        if let Some(branch) = head.shorthand() {
+            Ok(String::from(branch))
+        } else {
+            Ok(String::from("*INVALID"))
+        }
+    } else {
+        Ok(String::from("*DETACHED"))
+    }
+}
+
+pub fn set_sparse_checkout(repo: &Repository, patterns: &[String]) -> Result<(), git2::Error> {
+    let patterns: Vec<&str> = patterns
+        .iter()
+        .map(|pattern| pattern.trim_matches('/'))
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+
+    let tree = repo.head()?.peel_to_tree()?;
+    let mut index = repo.index()?;
+
+    index.read_tree(&tree)?;
+
+    let entries: Vec<git2::IndexEntry> = index.iter().collect();
+
+    for mut entry in entries {
+        let path = String::from_utf8_lossy(&entry.path).into_owned();
+        let selected = patterns.iter().any(|pattern| {
+            path == *pattern
+                || path
+                    .strip_prefix(pattern)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+
+        if !selected {
+            entry.flags |= git2::IndexEntryFlag::EXTENDED.bits();
+            entry.flags_extended |= git2::IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+            index.add(&entry)?;
+        }
+    }
+
+    index.write()?;
+
+    let mut checkout_options = git2::build::CheckoutBuilder::new();
+
+    for pattern in &patterns {
+        checkout_options.path(pattern);
+    }
+
+    repo.checkout_index(Some(&mut index), Some(checkout_options.force()))?;
+
+    let sparse_file = repo.path().join("info").join("sparse-checkout");
+    let sparse_data: String = patterns
+        .iter()
+        .map(|pattern| format!("/{pattern}\n"))
+        .collect();
+
+    fs::create_dir_all(repo.path().join("info"))
+        .and_then(|()| fs::write(sparse_file, sparse_data))
+        .map_err(|error| git2::Error::from_str(&error.to_string()))?;
+
+    repo.config()?.set_bool("core.sparseCheckout", true)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+fn git_command(repo_path: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(args)
+        .output()
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => anyhow!(
+                "git {} requires a git executable in PATH",
+                args.first().unwrap_or(&"")
+            ),
+            _ => error.into(),
+        })?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(anyhow!(
+            "git {}: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn maintain_repo(destination: &str, prune: &str, interval: Option<u64>) -> Result<Option<u64>> {
+    let repo = Repository::open(destination)?;
+    let mut config = repo.config()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let last = config
+        .get_i64("dorst.maintenance")
+        .map_or(0, |last| last.try_into().unwrap_or_default());
+
+    if interval.is_some_and(|days| now.saturating_sub(last) < days * 86400) {
+        return Ok(None);
+    }
+
+    let size = dir_size(repo.path());
+
+    git_command(repo.path(), &["gc", "--quiet", &format!("--prune={prune}")])?;
+    config.set_i64("dorst.maintenance", now.try_into()?)?;
+
+    Ok(Some(size.saturating_sub(dir_size(repo.path()))))
+}
+
+#[cfg(feature = "cli")]
+pub fn verify_mirror(destination: &Path) -> Result<()> {
+    let mirror = Repository::open_bare(destination)?;
+
+    if !mirror
+        .config()?
+        .get_bool("remote.origin.mirror")
+        .unwrap_or(false)
+    {
+        return Err(anyhow!("remote.origin.mirror is not set"));
+    }
+
+    if mirror.references()?.next().is_none() {
+        return Err(anyhow!("Empty mirror"));
+    }
+
+    mirror
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|error| anyhow!("Invalid HEAD: {}", error.message()))?;
+
+    git_command(
+        mirror.path(),
+        &["fsck", "--full", "--no-progress", "--no-dangling"],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn archive_tree(
+    destination: &Path,
+    output: &Path,
+    format: ArchiveFormat,
+    encryption: Option<&Encryption>,
+) -> Result<Option<PathBuf>> {
+    let repo = Repository::open(destination)?;
+    let name = destination.file_stem().unwrap().to_string_lossy();
+    // Source clones track the default branch through `origin/HEAD`
+    let commit = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .or_else(|_| repo.head())?
+        .peel_to_commit()?;
+
+    fs::create_dir_all(output)?;
+
+    let output = fs::canonicalize(output)?;
+    let id = commit.id().to_string();
+    let date = git_command(
+        repo.path(),
+        &["show", "--no-patch", "--format=%cd", "--date=short", &id],
+    )?;
+
+    let file = output.join(format!(
+        "{name}-{}-{}.{}{}",
+        date.trim(),
+        &id[..12],
+        format.extension(),
+        sealed_suffix(encryption)
+    ));
+
+    if file.exists() {
+        return Ok(None);
+    }
+
+    let partial = staging_path(&repo, &file, encryption);
+
+    git_command(
+        repo.path(),
+        &[
+            "archive",
+            &format!("--format={}", format.extension()),
+            &format!("--prefix={name}/"),
+            "--output",
+            &partial.display().to_string(),
+            &id,
+        ],
+    )?;
+
+    seal(&partial, &file, encryption)?;
+
+    Ok(Some(file))
+}
+
+#[cfg(feature = "cli")]
+fn sealed_suffix(encryption: Option<&Encryption>) -> String {
+    encryption.map_or_else(String::new, |_| format!(".{}", crypto::EXTENSION))
+}
+
+// Unencrypted data is staged inside the repository, so it never reaches the output
+#[cfg(feature = "cli")]
+fn staging_path(repo: &Repository, file: &Path, encryption: Option<&Encryption>) -> PathBuf {
+    if encryption.is_some() {
+        repo.path().join("dorst-export.part")
+    } else {
+        file.with_extension("part")
+    }
+}
+
+#[cfg(feature = "cli")]
+fn seal(partial: &Path, file: &Path, encryption: Option<&Encryption>) -> Result<()> {
+    match encryption {
+        Some(encryption) => {
+            let result = crypto::encrypt_file(partial, file, encryption);
+
+            fs::remove_file(partial)?;
+            result
+        }
+        None => Ok(fs::rename(partial, file)?),
+    }
+}
+
+// Objects are hardlinked (`git clone --local`), so generations share storage with each
+// other and stay intact when the mirror is repacked or pruned
+#[cfg(feature = "cli")]
+pub fn snapshot_mirror(destination: &Path, snapshot: &Path) -> Result<bool> {
+    if snapshot.exists() {
+        return Ok(false);
+    }
+
+    let mirror = Repository::open_bare(destination)?;
+    let partial = snapshot.with_extension("part");
+
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+
+    fs::create_dir_all(snapshot.parent().unwrap())?;
+    git_command(
+        mirror.path(),
+        &[
+            "clone",
+            "--mirror",
+            "--local",
+            "--quiet",
+            ".",
+            &fs::canonicalize(snapshot.parent().unwrap())?
+                .join(partial.file_name().unwrap())
+                .display()
+                .to_string(),
+        ],
+    )?;
+
+    if let Some(url) = mirror.find_remote("origin")?.url() {
+        Repository::open_bare(&partial)?.remote_set_url("origin", url)?;
+    }
+
+    fs::rename(partial, snapshot)?;
+
+    Ok(true)
+}
+
+#[cfg(feature = "cli")]
+#[derive(Default, Serialize, Deserialize)]
+pub struct Chain {
+    pub url: Option<String>,
+    pub head: Option<String>,
+    pub tips: BTreeMap<String, String>,
+    pub bundles: Vec<ChainBundle>,
+}
+
 #[cfg(feature = "cli")]
-use crate::util::get_name;
+#[derive(Serialize, Deserialize)]
+pub struct ChainBundle {
+    pub file: String,
+    pub sha256: String,
+    pub date: u64,
+    pub tips: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "cli")]
+impl Chain {
+    pub fn path(output: &Path, name: &str) -> PathBuf {
+        output.join(format!("{name}.chain.yml"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let partial = path.with_extension("yml.part");
+
+        fs::write(&partial, serde_yaml::to_string(self)?)?;
+        fs::rename(partial, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn ref_tips(repo: &Repository) -> Result<BTreeMap<String, String>> {
+    let mut tips = BTreeMap::new();
+
+    for reference in repo.references()?.flatten() {
+        if let (Some(name), Some(oid)) = (reference.name(), reference.target()) {
+            tips.insert(name.to_owned(), oid.to_string());
+        }
+    }
+
+    Ok(tips)
+}
+
+#[cfg(feature = "cli")]
+pub fn bundle_mirror(
+    destination: &Path,
+    output: &Path,
+    incremental: bool,
+    encryption: Option<&Encryption>,
+) -> Result<Option<u64>> {
+    let mirror = Repository::open_bare(destination)?;
+    let name = destination.file_stem().unwrap().to_string_lossy();
+    let chain_path = Chain::path(output, &name);
+    let tips = ref_tips(&mirror)?;
+
+    if tips.is_empty() {
+        return Err(anyhow!("Empty mirror"));
+    }
+
+    let previous = if chain_path.exists() {
+        Some(Chain::read(&chain_path)?)
+    } else {
+        None
+    };
+
+    let mut stale = Vec::new();
+    let mut chain = match previous {
+        Some(chain) if incremental => chain,
+        previous => {
+            // A full export starts a new chain, older bundles are removed once it is written
+            stale.extend(
+                previous
+                    .into_iter()
+                    .flat_map(|chain| chain.bundles)
+                    .map(|bundle| bundle.file),
+            );
+
+            Chain::default()
+        }
+    };
+
+    chain.url = mirror
+        .find_remote("origin")
+        .ok()
+        .and_then(|remote| remote.url().map(String::from));
+    chain.head = mirror
+        .find_reference("HEAD")
+        .ok()
+        .and_then(|head| head.symbolic_target().map(String::from));
+
+    // Objects reachable from previously exported tips are already in the chain
+    let mut exported: Vec<git2::Oid> = chain
+        .bundles
+        .iter()
+        .flat_map(|bundle| bundle.tips.values())
+        .filter_map(|oid| git2::Oid::from_str(oid).ok())
+        .filter(|oid| mirror.find_object(*oid, None).is_ok())
+        .collect();
+
+    exported.sort();
+    exported.dedup();
+
+    let exported_commits: Vec<git2::Oid> = exported
+        .iter()
+        .filter_map(|oid| mirror.find_object(*oid, None).ok()?.peel_to_commit().ok())
+        .map(|commit| commit.id())
+        .collect();
+
+    // New refs at already exported commits (e.g. a branch from an old commit) add no objects
+    let reachable = |oid: git2::Oid| {
+        exported.contains(&oid)
+            || mirror.find_commit(oid).is_ok()
+                && exported_commits.iter().any(|exported| {
+                    *exported == oid || mirror.graph_descendant_of(*exported, oid).unwrap_or(false)
+                })
+    };
+
+    let new_tips: BTreeMap<String, String> = tips
+        .iter()
+        .filter(|(_, oid)| !git2::Oid::from_str(oid).is_ok_and(reachable))
+        .map(|(name, oid)| (name.clone(), oid.clone()))
+        .collect();
+    let exclude: Vec<String> = exported.iter().map(|oid| format!("^{oid}")).collect();
+
+    chain.tips = tips;
+
+    if new_tips.is_empty() {
+        chain.write(&chain_path)?;
+        return Ok(None);
+    }
+
+    let file = if chain.bundles.is_empty() {
+        format!("{name}.bundle{}", sealed_suffix(encryption))
+    } else {
+        format!(
+            "{name}.{}.bundle{}",
+            chain.bundles.len(),
+            sealed_suffix(encryption)
+        )
+    };
+
+    let bundle = output.join(&file);
+    let partial = staging_path(&mirror, &bundle, encryption);
+    let partial_path = partial.display().to_string();
+    let mut args = vec!["bundle", "create", "--quiet", &partial_path, "--all"];
+
+    args.extend(exclude.iter().map(String::as_str));
+    git_command(mirror.path(), &args)?;
+    seal(&partial, &bundle, encryption)?;
+
+    let sha256 = file_checksum(&bundle)?;
+
+    fs::write(
+        output.join(format!("{file}.sha256")),
+        format!("{sha256}  {file}\n"),
+    )?;
+
+    for stale in stale.iter().filter(|stale| **stale != file) {
+        fs::remove_file(output.join(stale)).ok();
+        fs::remove_file(output.join(format!("{stale}.sha256"))).ok();
+    }
+
+    chain.bundles.push(ChainBundle {
+        file,
+        sha256,
+        date: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        tips: new_tips,
+    });
+
+    chain.write(&chain_path)?;
+
+    Ok(Some(fs::metadata(bundle)?.len()))
+}
+
+#[cfg(feature = "cli")]
+pub fn unbundle_chain(
+    chain_path: &Path,
+    destination: &Path,
+    encryption: Option<&Encryption>,
+) -> Result<()> {
+    let chain = Chain::read(chain_path)?;
+    let dir = fs::canonicalize(chain_path.parent().unwrap_or(Path::new(".")))?;
+
+    if chain.bundles.is_empty() {
+        return Err(anyhow!("Empty chain"));
+    }
+
+    let mirror = Repository::init_bare(destination)?;
+    let result = (|| -> Result<()> {
+        for bundle in &chain.bundles {
+            let path = dir.join(&bundle.file);
+
+            if file_checksum(&path)? != bundle.sha256 {
+                return Err(anyhow!("Checksum mismatch: {}", bundle.file));
+            }
+
+            let sealed = path.extension().is_some_and(|ext| ext == crypto::EXTENSION);
+
+            let path = if sealed {
+                let encryption = encryption.ok_or_else(|| {
+                    anyhow!("{} is encrypted (no encryption settings)", bundle.file)
+                })?;
+                let plain = mirror.path().join("dorst-import.bundle");
+
+                crypto::decrypt_file(&path, &plain, encryption)?;
+                plain
+            } else {
+                path
+            };
+
+            let fetched = git_command(
+                mirror.path(),
+                &[
+                    "fetch",
+                    "--quiet",
+                    &path.display().to_string(),
+                    "+refs/*:refs/*",
+                ],
+            );
+
+            if sealed {
+                fs::remove_file(&path)?;
+            }
+
+            fetched?;
+        }
+
+        for reference in mirror.references()?.flatten() {
+            if let Some(name) = reference.name() {
+                if !chain.tips.contains_key(name) {
+                    mirror.find_reference(name)?.delete()?;
+                }
+            }
+        }
 
-#[cfg(feature = "cli")]
-use std::io::{self, Write};
-use std::path::Path;
+        for (name, oid) in &chain.tips {
+            mirror.reference(name, git2::Oid::from_str(oid)?, true, "dorst: unbundle")?;
+        }
 
-pub fn set_callbacks(git_config: &git2::Config) -> RemoteCallbacks {
-    let mut callbacks = RemoteCallbacks::new();
+        if let Some(head) = &chain.head {
+            mirror.set_head(head)?;
+        }
 
-    callbacks.credentials(move |url, username_from_url, allowed_types| {
-        if allowed_types.is_user_pass_plaintext() {
-            Cred::credential_helper(git_config, url, username_from_url)
-        } else if allowed_types.is_ssh_key() {
-            match username_from_url {
-                Some(username) => Cred::ssh_key_from_agent(username),
-                None => Err(git2::Error::from_str("Could not extract username from URL")),
-            }
-        } else {
-            Cred::default()
+        if let Some(url) = &chain.url {
+            mirror.remote_with_fetch("origin", url, "+refs/*:refs/*")?;
+            mirror.config()?.set_bool("remote.origin.mirror", true)?;
         }
-    });
 
-    callbacks
+        Ok(())
+    })();
+
+    if result.is_err() {
+        fs::remove_dir_all(destination).ok();
+    }
+
+    result
 }
 
-pub fn set_default_branch(mirror: &Repository) -> Result<(), git2::Error> {
-    let remote = mirror.find_remote("origin")?;
-    let remote_branch = remote.name().unwrap();
-    let remote_branch_ref = mirror.resolve_reference_from_short_name(remote_branch)?;
-    let remote_branch_name = remote_branch_ref
-        .name()
-        .ok_or_else(|| git2::Error::from_str("No default branch"));
+#[cfg(feature = "cli")]
+fn file_checksum(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = openssl::sha::Sha256::new();
+    let mut buffer = [0; 65536];
 
-    let branch = remote_branch_name?.to_owned();
+    loop {
+        let read = io::Read::read(&mut file, &mut buffer)?;
 
-    mirror.set_head(&branch)?;
+        if read == 0 {
+            break;
+        }
 
-    Ok(())
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finish()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
 }
 
-pub fn current_branch(destination: std::path::PathBuf) -> Result<String, git2::Error> {
-    let repo = Repository::open(destination)?;
-    let head = repo.head()?;
+#[cfg(feature = "cli")]
+fn alternates(repo: &Repository) -> PathBuf {
+    repo.path().join("objects").join("info").join("alternates")
+}
 
-    if head.is_branch() {
-        if let Some(branch) = head.shorthand() {
-            Ok(String::from(branch))
-        } else {
-            Ok(String::from("*INVALID"))
-        }
-    } else {
-        Ok(String::from("*DETACHED"))
+#[cfg(feature = "cli")]
+pub fn shares_objects(destination_clone: &str, destination_backup: &str) -> Result<bool> {
+    let Ok(repo) = Repository::open(destination_clone) else {
+        return Ok(false);
+    };
+    let mirror = Repository::open_bare(destination_backup)?;
+    let alternate = fs::canonicalize(mirror.path().join("objects"))?
+        .display()
+        .to_string();
+
+    Ok(fs::read_to_string(alternates(&repo))
+        .is_ok_and(|data| data.lines().any(|line| line == alternate)))
+}
+
+#[cfg(feature = "cli")]
+pub fn share_objects(destination_clone: &str, destination_backup: &str) -> Result<()> {
+    let repo = Repository::open(destination_clone)?;
+    let mirror = Repository::open_bare(destination_backup)?;
+    let mirror_objects = fs::canonicalize(mirror.path().join("objects"))?;
+    let alternates = alternates(&repo);
+    let alternate = mirror_objects.display().to_string();
+
+    if !shares_objects(destination_clone, destination_backup)? {
+        let mut data = fs::read_to_string(&alternates).unwrap_or_default();
+
+        data.push_str(&format!("{alternate}\n"));
+        fs::create_dir_all(alternates.parent().unwrap())?;
+        fs::write(&alternates, data)?;
     }
+
+    git_command(mirror.path(), &["repack", "-d", "-q"])?;
+    git_command(repo.path(), &["repack", "-a", "-d", "-l", "-q"])?;
+
+    Ok(())
 }
 
 pub fn clone_repo(
-    target: &str,
+    target: &Target,
     destination: &str,
     bare: bool,
+    source: Option<&str>,
     #[cfg(feature = "cli")] spinner: Option<&ProgressBar>,
     #[cfg(feature = "gui")] tx: &Option<Sender<RowMessage>>,
-    git_config: &git2::Config,
     #[cfg(feature = "cli")] silent: Option<bool>,
 ) -> Result<(), git2::Error> {
-    let mut callbacks = set_callbacks(git_config);
+    let git_config = git2::Config::open_default()?;
+    let watchdog = Watchdog::new(target.options.host.timeout.as_ref(), source.is_none());
+    let mut callbacks = set_callbacks(&git_config, &target.options.host, &target.url);
+    let headers = set_headers(&target.options.host, &target.url)?;
+    let options = &target.options;
+    let target = target.url.as_str();
     #[cfg(feature = "cli")]
     let target_name = get_name(target);
 
+    callbacks.transfer_progress(|stats| watchdog.check(&stats));
+    callbacks.sideband_progress(|_| watchdog.sideband());
+
     #[cfg(feature = "cli")]
     if silent == Some(false) {
         callbacks.transfer_progress(|stats| {
+            if !watchdog.check(&stats) {
+                return false;
+            }
+
             if stats.received_objects() == stats.total_objects() {
                 spinner.unwrap().set_message(format!(
                     "\x1b[1;35mpulling\x1b[0m \x1b[93m{target_name}\
@@ -106,6 +1279,10 @@ pub fn clone_repo(
         let _ = tx.clone().unwrap().send_blocking(RowMessage::Clone);
 
         callbacks.transfer_progress(|stats| {
+            if !watchdog.check(&stats) {
+                return false;
+            }
+
             if stats.received_objects() == stats.total_objects() {
                 let _ = tx.clone().unwrap().send_blocking(RowMessage::Deltas);
                 let indexed = stats.indexed_deltas() as f64;
@@ -138,11 +1315,18 @@ pub fn clone_repo(
 
         fetch_options
             .remote_callbacks(callbacks)
+            .proxy_options(set_proxy(&options.host)?)
+            .custom_headers(&headers.iter().map(String::as_str).collect::<Vec<_>>())
             .download_tags(AutotagOption::All);
 
         let mirror = builder
             .fetch_options(fetch_options)
-            .clone(target, Path::new(&destination))?;
+            .clone(source.unwrap_or(target), Path::new(&destination))
+            .map_err(|error| watchdog.error(error))?;
+
+        if source.is_some() {
+            mirror.remote_set_url("origin", target)?;
+        }
 
         mirror.config()?.set_bool("remote.origin.mirror", true)?;
         set_default_branch(&mirror)?;
@@ -150,41 +1334,83 @@ pub fn clone_repo(
         Ok(())
     } else {
         let mut fetch_options = FetchOptions::new();
-        let checkout_options = git2::build::CheckoutBuilder::new();
+        let mut checkout_options = git2::build::CheckoutBuilder::new();
+
+        if !options.sparse.is_empty() {
+            checkout_options.dry_run();
+        }
 
-        fetch_options.remote_callbacks(callbacks);
+        fetch_options
+            .remote_callbacks(callbacks)
+            .proxy_options(set_proxy(&options.host)?)
+            .custom_headers(&headers.iter().map(String::as_str).collect::<Vec<_>>());
 
-        git2::build::RepoBuilder::new()
+        let repo = git2::build::RepoBuilder::new()
             .fetch_options(fetch_options)
             .with_checkout(checkout_options)
-            .clone(target, Path::new(&destination))?;
+            .clone(source.unwrap_or(target), Path::new(&destination))
+            .map_err(|error| watchdog.error(error))?;
+
+        if source.is_some() {
+            repo.remote_set_url("origin", target)?;
+        }
+
+        if !options.sparse.is_empty() {
+            set_sparse_checkout(&repo, &options.sparse)?;
+        }
 
         Ok(())
     }
 }
 
 pub fn fetch_repo(
-    target: &str,
+    target: &Target,
     repo: &Repository,
     mirror: bool,
+    source: Option<&str>,
     #[cfg(feature = "cli")] spinner: Option<&ProgressBar>,
     #[cfg(feature = "gui")] tx: &Option<Sender<RowMessage>>,
-    git_config: &git2::Config,
     #[cfg(feature = "cli")] silent: Option<bool>,
 ) -> Result<(), git2::Error> {
+    let git_config = git2::Config::open_default()?;
+    let watchdog = Watchdog::new(target.options.host.timeout.as_ref(), source.is_none());
+    let host = &target.options.host;
+    let headers = set_headers(host, &target.url)?;
+    let target = target.url.as_str();
     #[cfg(feature = "cli")]
     let target_name = get_name(target);
 
     {
-        let mut callbacks = set_callbacks(git_config);
+        let mut callbacks = set_callbacks(&git_config, host, target);
         let mut fetch_options = FetchOptions::new();
-        let mut remote = repo
-            .find_remote("origin")
-            .or_else(|_| repo.remote_anonymous(target))?;
+
+        callbacks.transfer_progress(|stats| watchdog.check(&stats));
+        callbacks.sideband_progress(|_| watchdog.sideband());
+        let mut remote = match source {
+            Some(source) => repo.remote_anonymous(source)?,
+            None => repo
+                .find_remote("origin")
+                .or_else(|_| repo.remote_anonymous(target))?,
+        };
+
+        let refspecs: Vec<String> = if source.is_some() {
+            repo.find_remote("origin")?
+                .fetch_refspecs()?
+                .iter()
+                .flatten()
+                .map(String::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         #[cfg(feature = "cli")]
         if silent == Some(false) {
             callbacks.sideband_progress(|data| {
+                if !watchdog.sideband() {
+                    return false;
+                }
+
                 spinner.unwrap().set_message(format!(
                     "\x1b[1;35mpulling\x1b[0m \x1b[93m{target_name}\
                      \x1b[0m remote: {}",
@@ -211,6 +1437,10 @@ pub fn fetch_repo(
             });
 
             callbacks.transfer_progress(|stats| {
+                if !watchdog.check(&stats) {
+                    return false;
+                }
+
                 if stats.received_objects() == stats.total_objects() {
                     spinner.unwrap().set_message(format!(
                         "\x1b[1;35mpulling\x1b[0m \x1b[93m{target_name}\
@@ -240,6 +1470,10 @@ pub fn fetch_repo(
             let _ = tx.clone().unwrap().send_blocking(RowMessage::Fetch);
 
             callbacks.transfer_progress(|stats| {
+                if !watchdog.check(&stats) {
+                    return false;
+                }
+
                 if stats.received_objects() == stats.total_objects() {
                     let _ = tx.clone().unwrap().send_blocking(RowMessage::Deltas);
                     let indexed = stats.indexed_deltas() as f64;
@@ -263,8 +1497,13 @@ pub fn fetch_repo(
             });
         }
 
-        fetch_options.remote_callbacks(callbacks);
-        remote.download(&[] as &[&str], Some(&mut fetch_options))?;
+        fetch_options
+            .remote_callbacks(callbacks)
+            .proxy_options(set_proxy(host)?)
+            .custom_headers(&headers.iter().map(String::as_str).collect::<Vec<_>>());
+        remote
+            .download(&refspecs, Some(&mut fetch_options))
+            .map_err(|error| watchdog.error(error))?;
 
         {
             #[cfg(feature = "cli")]
@@ -304,9 +1543,34 @@ pub fn fetch_repo(
             }
         }
 
+        let default_branch = remote
+            .default_branch()
+            .ok()
+            .and_then(|branch| branch.as_str().map(String::from));
+
         remote.disconnect()?;
         remote.update_tips(None, true, AutotagOption::Unspecified, None)?;
 
+        let default_branch = default_branch
+            .map(|branch| track_default_branch(repo, &branch, mirror))
+            .transpose()?
+            .flatten();
+
+        #[cfg(feature = "cli")]
+        if let (Some(default_branch), Some(false)) = (&default_branch, silent) {
+            spinner.unwrap().set_prefix(format!(
+                "{} \x1b[33m(default branch is {default_branch})\x1b[0m",
+                spinner.unwrap().prefix()
+            ));
+        }
+        #[cfg(feature = "gui")]
+        if default_branch.is_some() && tx.is_some() {
+            let _ = tx
+                .clone()
+                .unwrap()
+                .send_blocking(RowMessage::DefaultBranch(String::from(target)));
+        }
+
         let local_oid = repo.refname_to_id("HEAD")?;
         let remote_oid = repo.refname_to_id("FETCH_HEAD")?;
 
@@ -328,45 +1592,520 @@ pub fn fetch_repo(
     Ok(())
 }
 
+pub fn push_mirror(destination: &Path, remote: &Target) -> Result<()> {
+    let host = &remote.options.host;
+    let mirror = Repository::open_bare(destination)?;
+    let url = if get_host(&remote.url).is_none() && !remote.url.contains("://") {
+        let path = expand_path(&remote.url);
+
+        if !Path::new(&path).exists() {
+            let head = mirror.find_reference("HEAD")?;
+
+            if let Some(branch) = head.symbolic_target() {
+                Repository::init_bare(&path)?.set_head(branch)?;
+            }
+        }
+
+        local_url(&path)?
+    } else {
+        remote.url.clone()
+    };
+
+    let local_refs: Vec<String> = mirror
+        .references()?
+        .flatten()
+        .filter(|reference| reference.kind() == Some(git2::ReferenceType::Direct))
+        .filter_map(|reference| reference.name().map(String::from))
+        .collect();
+
+    if local_refs.is_empty() {
+        return Err(anyhow!("Empty mirror"));
+    }
+
+    let mut anonymous = mirror.remote_anonymous(&url)?;
+    let refspecs: Vec<String> = local_refs
+        .iter()
+        .map(|name| format!("+{name}:{name}"))
+        .collect();
+
+    push_refs(&mut anonymous, &refspecs, host)?;
+
+    // Listing an empty remote is not safe, so stale refs are pruned after the update
+    let remote_refs: Vec<String> = {
+        let git_config = git2::Config::open_default()?;
+        let connection = anonymous.connect_auth(
+            Direction::Push,
+            Some(set_callbacks(&git_config, host, &url)),
+            Some(set_proxy(host)?),
+        )?;
+
+        connection
+            .list()?
+            .iter()
+            .map(|head| head.name().to_owned())
+            .filter(|name| name.starts_with("refs/") && !local_refs.contains(name))
+            .map(|name| format!(":{name}"))
+            .collect()
+    };
+
+    if remote_refs.is_empty() {
+        Ok(())
+    } else {
+        push_refs(&mut anonymous, &remote_refs, host)
+    }
+}
+
+fn push_refs(remote: &mut Remote, refspecs: &[String], host: &HostOptions) -> Result<()> {
+    let git_config = git2::Config::open_default()?;
+    let url = remote.url().unwrap_or_default().to_owned();
+    let headers = set_headers(host, &url)?;
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let rejected = RefCell::new(Vec::new());
+
+    {
+        let mut callbacks = set_callbacks(&git_config, host, &url);
+        let mut push_options = PushOptions::new();
+
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(status) = status {
+                rejected.borrow_mut().push(format!("{refname} ({status})"));
+            }
+
+            Ok(())
+        });
+
+        push_options
+            .remote_callbacks(callbacks)
+            .proxy_options(set_proxy(host)?)
+            .custom_headers(&headers);
+
+        remote.push(refspecs, Some(&mut push_options))?;
+    }
+
+    let rejected = rejected.into_inner();
+
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Rejected {}", rejected.join(", ")))
+    }
+}
+
+// libgit2 exposes no redirect callback. Its HTTP transport logs "<method> request to <url>"
+// at Debug level for every request, including the ones that follow redirects, so tracing
+// stays enabled process-wide and this depends on the exact wording of that message.
+fn trace_request(_level: TraceLevel, message: &str) {
+    if let Some((_, url)) = message.split_once(" request to ") {
+        LAST_REQUEST.with(|request| *request.borrow_mut() = Some(url.to_owned()));
+    }
+}
+
+fn split_credentials(url: &str) -> (Option<&str>, String) {
+    url.split_once("://")
+        .and_then(|(scheme, rest)| {
+            let (credentials, rest) = rest.split_once('@')?;
+
+            (!credentials.contains('/')).then(|| (Some(credentials), format!("{scheme}://{rest}")))
+        })
+        .unwrap_or((None, url.to_owned()))
+}
+
+fn redirected_url(url: &str) -> Option<String> {
+    let request = LAST_REQUEST.with(|request| request.borrow_mut().take())?;
+    let location = ["/info/refs?service=git-upload-pack", "/git-upload-pack"]
+        .iter()
+        .find_map(|suffix| request.strip_suffix(suffix))?;
+
+    let (credentials, url) = split_credentials(url);
+    let (_, location) = split_credentials(location);
+    let location = if url.ends_with(".git") {
+        location.as_str()
+    } else {
+        location.trim_end_matches(".git")
+    }
+    .trim_end_matches('/');
+
+    if location == url.trim_end_matches('/') {
+        return None;
+    }
+
+    Some(match (credentials, location.split_once("://")) {
+        (Some(credentials), Some((scheme, rest))) => format!("{scheme}://{credentials}@{rest}"),
+        _ => location.to_owned(),
+    })
+}
+
+fn record_redirect(destination: &str, url: &str) -> Result<Option<String>, git2::Error> {
+    let mut config = Repository::open(destination)?.config()?;
+
+    match redirected_url(url) {
+        Some(location) => {
+            config.set_str("dorst.redirect", &location)?;
+            Ok(Some(location))
+        }
+        None => {
+            let _ = config.remove("dorst.redirect");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn redirect(destination: &str) -> Result<Option<String>, git2::Error> {
+    let config = Repository::open(destination)?.config()?.snapshot()?;
+
+    Ok(config.get_string("dorst.redirect").ok())
+}
+
+#[cfg(feature = "cli")]
+pub fn apply_redirect(destination: &str, location: &str) -> Result<(), git2::Error> {
+    let repo = Repository::open(destination)?;
+
+    repo.remote_set_url("origin", location)?;
+    let _ = repo.config()?.remove("dorst.redirect");
+
+    Ok(())
+}
+
+fn local_url(path: &str) -> Result<String> {
+    let path = fs::canonicalize(path)?;
+    let path = path.display().to_string().replace('%', "%25");
+
+    Ok(format!("file://{path}"))
+}
+
 pub fn process_target(
     destination: &str,
-    target: &str,
+    target: &Target,
     mirror: bool,
+    source: Option<&str>,
     #[cfg(feature = "cli")] spinner: Option<&ProgressBar>,
     #[cfg(feature = "gui")] tx: &Option<Sender<RowMessage>>,
     #[cfg(feature = "cli")] silent: Option<bool>,
 ) -> Result<()> {
-    let git_config = git2::Config::open_default()?;
+    let source = source.map(local_url).transpose()?;
+    let retry = target.options.host.retry.clone().unwrap_or_default();
+    let _slot = target
+        .options
+        .host
+        .connections
+        .zip(get_host(&target.url))
+        .filter(|(connections, _)| source.is_none() && *connections > 0)
+        .map(|(connections, host)| HostSlot::acquire(host, connections));
+    let existing = Path::new(&destination).exists();
+    let mut attempt = 1;
+
+    TRACE.call_once(|| {
+        git2::trace_set(TraceLevel::Debug, trace_request);
+    });
 
-    if Path::new(&destination).exists() {
-        let repo = Repository::open(destination)?;
+    let note = existing
+        .then(|| reconcile_url(destination, target))
+        .transpose()?
+        .flatten();
 
-        fetch_repo(
-            target,
-            &repo,
-            mirror,
-            #[cfg(feature = "cli")]
-            spinner,
-            #[cfg(feature = "gui")]
-            tx,
-            &git_config,
-            #[cfg(feature = "cli")]
-            silent,
-        )?;
+    #[cfg(feature = "cli")]
+    if let (Some(note), Some(spinner)) = (&note, spinner) {
+        if !spinner.prefix().contains(note) {
+            spinner.set_prefix(format!("{} \x1b[33m({note})\x1b[0m", spinner.prefix()));
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    if let (Some(_), Some(tx)) = (&note, tx) {
+        let _ = tx.send_blocking(RowMessage::UrlMismatch(target.url.clone()));
+    }
+
+    loop {
+        LAST_REQUEST.with(|request| request.borrow_mut().take());
+
+        let result = if Path::new(&destination).exists() {
+            Repository::open(destination).and_then(|repo| {
+                fetch_repo(
+                    target,
+                    &repo,
+                    mirror,
+                    source.as_deref(),
+                    #[cfg(feature = "cli")]
+                    spinner,
+                    #[cfg(feature = "gui")]
+                    tx,
+                    #[cfg(feature = "cli")]
+                    silent,
+                )
+            })
+        } else {
+            clone_repo(
+                target,
+                destination,
+                mirror,
+                source.as_deref(),
+                #[cfg(feature = "cli")]
+                spinner,
+                #[cfg(feature = "gui")]
+                tx,
+                #[cfg(feature = "cli")]
+                silent,
+            )
+        };
+
+        match result {
+            Ok(()) => {
+                let location = source
+                    .is_none()
+                    .then(|| record_redirect(destination, &target.url))
+                    .transpose()?
+                    .flatten();
+
+                #[cfg(feature = "cli")]
+                if let (Some(location), Some(spinner)) = (&location, spinner) {
+                    spinner.set_prefix(format!(
+                        "{} \x1b[33m(moved to {location})\x1b[0m",
+                        spinner.prefix()
+                    ));
+                }
+
+                #[cfg(feature = "gui")]
+                if let (Some(_), Some(tx)) = (&location, tx) {
+                    let _ = tx.send_blocking(RowMessage::Moved(target.url.clone()));
+                }
+
+                if mirror && existing {
+                    mark_orphaned(destination, false)?;
+                }
+
+                return Ok(());
+            }
+            Err(error) if mirror && existing && is_gone(&error) => {
+                mark_orphaned(destination, true)?;
+
+                #[cfg(feature = "gui")]
+                if let Some(tx) = tx {
+                    let _ = tx.send_blocking(RowMessage::Orphaned(target.url.clone()));
+                }
+
+                return Err(Orphaned { error }.into());
+            }
+            Err(error)
+                if attempt < retry.attempts
+                    && error_kind(&error).is_some_and(|kind| retry.errors.contains(&kind)) =>
+            {
+                let delay = retry.backoff(attempt);
+
+                #[cfg(feature = "cli")]
+                if let Some(spinner) = spinner {
+                    spinner.set_message(format!(
+                        "\x1b[1;33mretrying\x1b[0m \x1b[93m{}\x1b[0m in {}s ({attempt}/{})",
+                        get_name(&target.url),
+                        delay.as_secs(),
+                        retry.attempts - 1
+                    ));
+                }
+
+                #[cfg(feature = "gui")]
+                if let Some(tx) = tx {
+                    let _ = tx.send_blocking(RowMessage::Reset);
+                }
+
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(error) if attempt > 1 => {
+                return Err(RetryFailure {
+                    attempts: attempt,
+                    error,
+                }
+                .into())
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+fn mark_orphaned(destination: &str, orphaned: bool) -> Result<(), git2::Error> {
+    let mut config = Repository::open(destination)?.config()?;
+
+    if orphaned {
+        config.set_bool("dorst.orphaned", true)
     } else {
-        clone_repo(
-            target,
-            destination,
-            mirror,
-            #[cfg(feature = "cli")]
-            spinner,
-            #[cfg(feature = "gui")]
-            tx,
-            &git_config,
-            #[cfg(feature = "cli")]
-            silent,
-        )?;
+        let _ = config.remove("dorst.orphaned");
+        Ok(())
+    }
+}
+
+fn is_gone(error: &git2::Error) -> bool {
+    let message = error.message().to_lowercase();
+
+    error.code() == git2::ErrorCode::Auth
+        || [
+            "status code: 401",
+            "status code: 403",
+            "status code: 404",
+            "authentication replays",
+            "authentication required",
+            "repository not found",
+            "could not find repository",
+            "failed to resolve path",
+            "does not appear to be a git repository",
+            "access denied",
+            "permission denied",
+        ]
+        .iter()
+        .any(|keyword| message.contains(keyword))
+}
+
+fn reconcile_url(destination: &str, target: &Target) -> Result<Option<String>> {
+    let repo = Repository::open(destination)?;
+    let Some(url) = repo
+        .find_remote("origin")
+        .ok()
+        .and_then(|remote| remote.url().map(String::from))
+        .filter(|url| *url != target.url)
+    else {
+        return Ok(None);
     };
 
-    Ok(())
+    match target.options.host.url_mismatch.unwrap_or_default() {
+        UrlPolicy::Update => {
+            repo.remote_set_url("origin", &target.url)?;
+            Ok(Some(format!("origin moved from {url}")))
+        }
+        UrlPolicy::Warn => Ok(Some(format!("origin is {url}"))),
+        UrlPolicy::Fail => Err(anyhow!(
+            "origin URL {url} does not match the configured {}",
+            target.url
+        )),
+    }
+}
+
+fn error_kind(error: &git2::Error) -> Option<RetryError> {
+    let message = error.message().to_lowercase();
+
+    if message.contains("timed out") || message.contains("timeout") {
+        return Some(RetryError::Timeout);
+    }
+
+    match error.class() {
+        ErrorClass::Http if message.contains("status code: 5") => Some(RetryError::Server),
+        ErrorClass::Net | ErrorClass::Os | ErrorClass::Ssh | ErrorClass::Ssl
+            if [
+                "connect",
+                "reset",
+                "refused",
+                "broken pipe",
+                "resolve",
+                "early eof",
+                "send",
+                "receive",
+            ]
+            .iter()
+            .any(|keyword| message.contains(keyword)) =>
+        {
+            Some(RetryError::Connection)
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_error_kind() {
+    let error = git2::Error::new(
+        git2::ErrorCode::GenericError,
+        ErrorClass::Net,
+        "failed to connect to localhost: Connection refused",
+    );
+
+    assert!(error_kind(&error) == Some(RetryError::Connection));
+
+    let error = git2::Error::new(
+        git2::ErrorCode::GenericError,
+        ErrorClass::Http,
+        "unexpected http status code: 503",
+    );
+
+    assert!(error_kind(&error) == Some(RetryError::Server));
+
+    let error = git2::Error::new(
+        git2::ErrorCode::GenericError,
+        ErrorClass::Http,
+        "unexpected http status code: 404",
+    );
+
+    assert!(error_kind(&error).is_none());
+}
+
+#[test]
+fn test_fingerprint() {
+    let digest = sha256(b"dorst");
+    let hex: Vec<String> = digest.iter().map(|byte| format!("{byte:02X}")).collect();
+    let base64 = encode_block(&digest);
+
+    assert!(parse_fingerprint(&format!("sha256:{}", hex.join(":"))).unwrap() == digest);
+    assert!(
+        parse_fingerprint(&format!("SHA256:{}", base64.trim_end_matches('='))).unwrap() == digest
+    );
+    assert!(parse_fingerprint("SHA256:!").is_none());
+}
+
+#[test]
+fn test_known_host() {
+    assert!(known_host_match("example.com", "example.com"));
+    assert!(known_host_match("[example.com]:2222", "[example.com]:2222"));
+    assert!(!known_host_match("[example.com]:2222", "example.com"));
+    assert!(!known_host_match(
+        "[example.com]:2222",
+        "[example.com]:2200"
+    ));
+    assert!(known_host_name("example.com", Some(22)) == "example.com");
+    assert!(known_host_name("example.com", Some(2222)) == "[example.com]:2222");
+    assert!(known_host_match(
+        "|1|MDEyMzQ1Njc4OWFiY2RlZmdoaWo=|jaHXoMQTU/+rEgquOJTQzPGCF4I=",
+        "example.com"
+    ));
+    assert!(!known_host_match(
+        "|1|MDEyMzQ1Njc4OWFiY2RlZmdoaWo=|jaHXoMQTU/+rEgquOJTQzPGCF4I=",
+        "example.org"
+    ));
+}
+
+#[test]
+fn test_redirected_url() {
+    let record = |url: &str| {
+        LAST_REQUEST.with(|request| *request.borrow_mut() = Some(url.to_owned()));
+    };
+
+    record("https://example.com/foo/bar/info/refs?service=git-upload-pack");
+    assert!(redirected_url("https://example.com/foo/bar").is_none());
+
+    record("https://example.com/baz/bar.git/info/refs?service=git-upload-pack");
+    assert!(
+        redirected_url("https://git@example.com/foo/bar")
+            == Some(String::from("https://git@example.com/baz/bar"))
+    );
+
+    record("https://example.com/baz/bar.git/git-upload-pack");
+    assert!(
+        redirected_url("https://example.com/foo/bar.git")
+            == Some(String::from("https://example.com/baz/bar.git"))
+    );
+    assert!(redirected_url("https://example.com/foo/bar").is_none());
+}
+
+#[test]
+fn test_is_gone() {
+    let error = git2::Error::new(
+        git2::ErrorCode::GenericError,
+        ErrorClass::Http,
+        "unexpected http status code: 404",
+    );
+
+    assert!(is_gone(&error));
+
+    let error = git2::Error::new(
+        git2::ErrorCode::GenericError,
+        ErrorClass::Http,
+        "unexpected http status code: 503",
+    );
+
+    assert!(!is_gone(&error));
 }
@@ -132,7 +132,7 @@ mod tests {
             .unwrap();
         }
 
-        fn last_commit(repo: &Repository) -> Commit {
+        fn last_commit(repo: &Repository) -> Commit<'_> {
             let obj = repo
                 .head()
                 .unwrap()
@@ -3,11 +3,18 @@ use gtk::subclass::prelude::*;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "logs")]
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use std::sync::{Arc, Mutex};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
-use crate::{git, gui::window::RowMessage};
+use crate::{
+    config::{Settings, Target, TargetOptions},
+    git,
+    gui::window::RowMessage,
+};
 
 mod imp;
 
@@ -48,7 +55,7 @@ impl RepoObject {
     }
 
     pub fn from_repo_data(repo_data: RepoData) -> Self {
-        Self::new(
+        let repo = Self::new(
             repo_data.name,
             repo_data.link,
             repo_data.branch,
@@ -56,24 +63,33 @@ impl RepoObject {
             repo_data.status,
             repo_data.error,
             repo_data.completed,
-        )
+        );
+
+        repo.imp().data.borrow_mut().options = repo_data.options;
+        repo
     }
 
     pub fn process_repo(
         &self,
         destination_clone: &str,
-        destination_backup: &str,
-        mirror: bool,
+        destination_backup: Option<&str>,
+        settings: &Settings,
         #[cfg(feature = "gui")] tx: Option<async_channel::Sender<RowMessage>>,
         #[cfg(feature = "logs")] logs: bool,
         active_threads: Arc<Mutex<u64>>,
     ) {
         let repo = self.clone();
-        let repo_link = self.link();
+        let mut repo_target = Target {
+            url: self.link(),
+            options: self.repo_data().options,
+        };
         #[cfg(feature = "logs")]
         let repo_name = self.name();
+        let mirror = destination_backup.is_some();
         let dest_clone = String::from(destination_clone);
-        let dest_backup = String::from(destination_backup);
+        let dest_backup = String::from(destination_backup.unwrap_or_default());
+
+        repo_target.resolve(settings);
 
         let (tx_repo, rx_repo) = async_channel::unbounded();
 
@@ -101,11 +117,73 @@ impl RepoObject {
         glib::MainContext::default().spawn_local(event_handler);
         gtk::gio::spawn_blocking(move || {
             let mut err_string = String::new();
+            let mut backup_err = String::new();
+            let mut source = None;
+
+            if mirror {
+                match git::process_target(
+                    &dest_backup,
+                    &repo_target,
+                    true,
+                    None,
+                    #[cfg(feature = "cli")]
+                    None,
+                    #[cfg(feature = "gui")]
+                    &tx,
+                    #[cfg(feature = "cli")]
+                    None,
+                ) {
+                    Ok(()) => {
+                        #[cfg(feature = "logs")]
+                        if logs {
+                            info!("Completed (backup): {repo_name}");
+                        }
+
+                        source = Some(dest_backup.as_str());
+
+                        for remote in &repo_target.options.push {
+                            if let Err(error) = git::push_mirror(Path::new(&dest_backup), remote) {
+                                #[cfg(feature = "logs")]
+                                if logs {
+                                    error!("Failed (push): {repo_name} - {error}");
+                                }
+
+                                if !backup_err.is_empty() {
+                                    backup_err.push(' ');
+                                }
+
+                                backup_err.push_str(&format!("push to {}: {error}", remote.url));
+                            }
+                        }
+                    }
+                    Err(error) if error.is::<git::Orphaned>() => {
+                        #[cfg(feature = "logs")]
+                        if logs {
+                            warn!("Orphaned (backup): {repo_name} - {error}");
+                        }
+
+                        source = Some(dest_backup.as_str());
+                    }
+                    Err(error) => {
+                        #[cfg(feature = "logs")]
+                        if logs {
+                            error!("Failed (backup): {repo_name} - {error}");
+                        }
+
+                        backup_err.push_str(&format!("backup: {error}"));
+                    }
+                }
+
+                let _ = tx.clone().unwrap().send_blocking(RowMessage::Finish);
+                let _ = tx.clone().unwrap().send_blocking(RowMessage::Reset);
+                let _ = tx_repo.send_blocking(RepoMessage::Reset);
+            }
 
             match git::process_target(
                 &dest_clone,
-                &repo_link,
+                &repo_target,
                 false,
+                source,
                 #[cfg(feature = "cli")]
                 None,
                 #[cfg(feature = "gui")]
@@ -133,40 +211,12 @@ impl RepoObject {
 
             let _ = tx.clone().unwrap().send_blocking(RowMessage::Finish);
 
-            if mirror {
-                let _ = tx.clone().unwrap().send_blocking(RowMessage::Reset);
-                let _ = tx_repo.send_blocking(RepoMessage::Reset);
-
-                match git::process_target(
-                    &dest_backup,
-                    &repo_link,
-                    true,
-                    #[cfg(feature = "cli")]
-                    None,
-                    #[cfg(feature = "gui")]
-                    &tx,
-                    #[cfg(feature = "cli")]
-                    None,
-                ) {
-                    Ok(()) => {
-                        #[cfg(feature = "logs")]
-                        if logs {
-                            info!("Completed (backup): {repo_name}");
-                        }
-
-                        let _ = tx_repo.send_blocking(RepoMessage::Ok);
-                    }
-                    Err(error) => {
-                        #[cfg(feature = "logs")]
-                        if logs {
-                            error!("Failed (backup): {repo_name} - {error}");
-                        }
-
-                        err_string.push_str(&format!(" backup: {error}"));
-                    }
+            if !backup_err.is_empty() {
+                if !err_string.is_empty() {
+                    err_string.push(' ');
                 }
 
-                let _ = tx.clone().unwrap().send_blocking(RowMessage::Finish);
+                err_string.push_str(&backup_err);
             }
 
             if !err_string.is_empty() {
@@ -188,4 +238,5 @@ pub struct RepoData {
     pub status: String,
     pub error: String,
     pub completed: bool,
+    pub options: TargetOptions,
 }
@@ -22,9 +22,10 @@ use std::{
 mod imp;
 
 use crate::{
+    config::{Settings, Target},
     git,
     gui::{preferences::DorstPreferences, repo_box::RepoBox, repo_object::RepoObject, RepoData},
-    util,
+    limit, util,
 };
 
 glib::wrapper! {
@@ -41,6 +42,10 @@ pub enum RowMessage {
     Fetch,
     Deltas,
     Updated(String),
+    DefaultBranch(String),
+    UrlMismatch(String),
+    Moved(String),
+    Orphaned(String),
     Finish,
 }
 
@@ -204,6 +209,45 @@ impl Window {
                     );
                 }
 
+                let orphaned_list_locked = window.imp().orphaned_list.lock().unwrap();
+
+                if !orphaned_list_locked.is_empty() {
+                    window.show_message(
+                        &format!("Orphaned repositories: {}", orphaned_list_locked.len()),
+                        4,
+                    );
+                }
+
+                let moved_list_locked = window.imp().moved_list.lock().unwrap();
+
+                if !moved_list_locked.is_empty() {
+                    window.show_message(
+                        &format!("Moved repositories: {}", moved_list_locked.len()),
+                        4,
+                    );
+                }
+
+                let mismatch_list_locked = window.imp().mismatch_list.lock().unwrap();
+
+                if !mismatch_list_locked.is_empty() {
+                    window.show_message(
+                        &format!("Remote URL mismatches: {}", mismatch_list_locked.len()),
+                        4,
+                    );
+                }
+
+                let default_list_locked = window.imp().default_list.lock().unwrap();
+
+                if !default_list_locked.is_empty() {
+                    window.show_message(
+                        &format!(
+                            "Clones on an old default branch: {}",
+                            default_list_locked.len()
+                        ),
+                        4,
+                    );
+                }
+
                 window.imp().progress_bar.set_fraction(1.0);
                 window.imp().revealer.set_reveal_child(false);
                 window.imp().button_source_dest.remove_css_class("with_bar");
@@ -477,6 +521,10 @@ impl Window {
     fn process_targets(&self) {
         self.controls_disabled(true);
         self.imp().updated_list.lock().unwrap().clear();
+        self.imp().default_list.lock().unwrap().clear();
+        self.imp().mismatch_list.lock().unwrap().clear();
+        self.imp().moved_list.lock().unwrap().clear();
+        self.imp().orphaned_list.lock().unwrap().clear();
         self.imp().errors_list.lock().unwrap().clear();
         self.imp().success_list.lock().unwrap().clear();
         self.imp().button_source_dest.add_css_class("with_bar");
@@ -536,8 +584,8 @@ impl Window {
 
                 repo.process_repo(
                     &destination_clone,
-                    &destination_backup,
-                    backups_enabled,
+                    backups_enabled.then_some(destination_backup.as_str()),
+                    &self.imp().settings.borrow(),
                     #[cfg(feature = "gui")]
                     Some(tx.clone()),
                     #[cfg(feature = "logs")]
@@ -570,6 +618,10 @@ impl Window {
                         if self.imp().updated_list.lock().unwrap().contains(&link) {
                             repo_object.set_status("updated");
                         }
+
+                        if self.imp().orphaned_list.lock().unwrap().contains(&link) {
+                            repo_object.set_status("orphaned");
+                        }
                     } else if self
                         .imp()
                         .errors_list
@@ -636,6 +688,10 @@ impl Window {
         let (tx, rx) = async_channel::unbounded();
         let repo = row.downcast::<RepoObject>().unwrap();
         let updated_list_clone = self.imp().updated_list.clone();
+        let default_list_clone = self.imp().default_list.clone();
+        let mismatch_list_clone = self.imp().mismatch_list.clone();
+        let moved_list_clone = self.imp().moved_list.clone();
+        let orphaned_list_clone = self.imp().orphaned_list.clone();
         let event_handler = async move {
             while let Ok(event) = rx.recv().await {
                 match event {
@@ -667,6 +723,22 @@ impl Window {
                     RowMessage::Updated(link) => {
                         updated_list_clone.lock().unwrap().push(link);
                     }
+                    RowMessage::DefaultBranch(link) => {
+                        default_list_clone.lock().unwrap().push(link);
+                    }
+                    RowMessage::Orphaned(link) => {
+                        orphaned_list_clone.lock().unwrap().push(link);
+                    }
+                    RowMessage::Moved(link) => {
+                        moved_list_clone.lock().unwrap().push(link);
+                    }
+                    RowMessage::UrlMismatch(link) => {
+                        let mut mismatch_list = mismatch_list_clone.lock().unwrap();
+
+                        if !mismatch_list.contains(&link) {
+                            mismatch_list.push(link);
+                        }
+                    }
                     RowMessage::Finish => {
                         repo.set_progress(1.0);
                     }
@@ -696,6 +768,7 @@ impl Window {
                     name.add_css_class("success");
                     name.remove_css_class("error");
                     name.remove_css_class("accent");
+                    name.remove_css_class("warning");
                     status_image.set_from_icon_name(Some("emblem-ok-symbolic"));
                     status_revealer.set_reveal_child(true);
                     branch_revealer.set_reveal_child(true);
@@ -704,11 +777,18 @@ impl Window {
                     name.add_css_class("accent");
                     name.remove_css_class("success");
                     name.remove_css_class("error");
+                    name.remove_css_class("warning");
                     status_image.set_from_icon_name(Some("emblem-default-symbolic"));
+                } else if repo_object.status() == "orphaned" {
+                    name.add_css_class("warning");
+                    name.remove_css_class("success");
+                    name.remove_css_class("accent");
+                    status_image.set_from_icon_name(Some("user-trash-symbolic"));
                 } else if repo_object.status() == "err" {
                     name.add_css_class("error");
                     name.remove_css_class("success");
                     name.remove_css_class("accent");
+                    name.remove_css_class("warning");
                     status_image.set_from_icon_name(Some("dialog-warning-symbolic"));
                     status_revealer.set_reveal_child(true);
                     branch_revealer.set_reveal_child(false);
@@ -717,6 +797,7 @@ impl Window {
                     name.remove_css_class("error");
                     name.remove_css_class("success");
                     name.remove_css_class("accent");
+                    name.remove_css_class("warning");
                     pb.set_fraction(0.0);
                     status_revealer.set_reveal_child(false);
                     branch_revealer.set_reveal_child(false);
@@ -737,6 +818,10 @@ impl Window {
                         if window.imp().updated_list.lock().unwrap().contains(&repo_object.link()) {
                             repo_object.set_status("updated");
                         }
+
+                        if window.imp().orphaned_list.lock().unwrap().contains(&repo_object.link()) {
+                            repo_object.set_status("orphaned");
+                        }
                     }
                 } else if repo_object.status() == "cloning"{
                     pb.add_css_class("clone");
@@ -882,30 +967,38 @@ impl Window {
                     .remove_css_class("suggested-action");
             }
 
+            if let Ok(settings) = serde_yaml::from_value::<Settings>(config.clone()) {
+                limit::set_rate(settings.rate_limit);
+                *self.imp().settings.borrow_mut() = settings;
+            }
+
             if let Some(targets) = config["targets"].as_sequence() {
                 let repo_objects: Vec<RepoObject> = targets
                     .iter()
                     .filter_map(|target| {
-                        target.as_str().map(|link| {
-                            let mut link_string = String::from(link);
-                            if link_string.ends_with('/') {
-                                link_string.pop();
-                            }
-
-                            if link_string.is_empty() {
-                                link_string.push_str("INVALID");
-                            }
-
-                            RepoData {
-                                name: util::get_name(&link_string).to_owned(),
-                                link: link_string,
-                                branch: String::new(),
-                                progress: 0.0,
-                                status: String::new(),
-                                error: String::new(),
-                                completed: false,
-                            }
-                        })
+                        serde_yaml::from_value::<Target>(target.clone())
+                            .ok()
+                            .map(|target| {
+                                let mut link_string = target.url;
+                                if link_string.ends_with('/') {
+                                    link_string.pop();
+                                }
+
+                                if link_string.is_empty() {
+                                    link_string.push_str("INVALID");
+                                }
+
+                                RepoData {
+                                    name: util::get_name(&link_string).to_owned(),
+                                    link: link_string,
+                                    branch: String::new(),
+                                    progress: 0.0,
+                                    status: String::new(),
+                                    error: String::new(),
+                                    completed: false,
+                                    options: target.options,
+                                }
+                            })
                     })
                     .map(RepoObject::from_repo_data)
                     .collect();
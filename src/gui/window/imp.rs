@@ -8,12 +8,13 @@ use glib::Properties;
 use serde_yaml::{Mapping, Sequence, Value};
 use std::{
     cell::{Cell, RefCell},
-    fs::File,
+    fs::{self, File},
     io::Write,
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 
+use crate::config::{Settings, Target};
 use crate::gui::window::RepoObject;
 use crate::gui::RepoData;
 use crate::util;
@@ -41,6 +42,7 @@ pub struct Window {
     pub repos_filtered: RefCell<FilterListModel>,
     pub source_directory: RefCell<String>,
     pub backup_directory: RefCell<PathBuf>,
+    pub settings: RefCell<Settings>,
     #[template_child]
     pub progress_bar: TemplateChild<ProgressBar>,
     #[template_child]
@@ -56,6 +58,10 @@ pub struct Window {
     pub color_scheme: Arc<Mutex<String>>,
     pub style_manager: StyleManager,
     pub updated_list: Arc<Mutex<Vec<String>>>,
+    pub default_list: Arc<Mutex<Vec<String>>>,
+    pub mismatch_list: Arc<Mutex<Vec<String>>>,
+    pub moved_list: Arc<Mutex<Vec<String>>>,
+    pub orphaned_list: Arc<Mutex<Vec<String>>>,
     pub errors_list: Arc<Mutex<Vec<String>>>,
     pub success_list: Arc<Mutex<Vec<String>>>,
     #[property(get, set)]
@@ -90,6 +96,7 @@ impl ObjectSubclass for Window {
             repos_filtered: RefCell::default(),
             source_directory: RefCell::new(String::new()),
             backup_directory: RefCell::new(PathBuf::new()),
+            settings: RefCell::default(),
             progress_bar: TemplateChild::default(),
             toast_overlay: TemplateChild::default(),
             revealer: TemplateChild::default(),
@@ -100,6 +107,10 @@ impl ObjectSubclass for Window {
             color_scheme: Arc::default(),
             style_manager: StyleManager::default(),
             updated_list: Arc::default(),
+            default_list: Arc::default(),
+            mismatch_list: Arc::default(),
+            moved_list: Arc::default(),
+            orphaned_list: Arc::default(),
             errors_list: Arc::default(),
             success_list: Arc::default(),
             completed: Cell::default(),
@@ -194,10 +205,20 @@ impl WindowImpl for Window {
 
         let mut target_sequence = Sequence::new();
         for repo_data in backup_data {
-            target_sequence.push(Value::String(repo_data.link));
+            let target = Target {
+                url: repo_data.link,
+                options: repo_data.options,
+            };
+
+            target_sequence.push(serde_yaml::to_value(target).unwrap());
         }
 
-        let mut yaml_mapping = Mapping::new();
+        let config_path = util::xdg_path().unwrap();
+        let mut yaml_mapping = fs::read_to_string(&config_path)
+            .ok()
+            .and_then(|data| serde_yaml::from_str::<Mapping>(&data).ok())
+            .unwrap_or_default();
+
         yaml_mapping.insert(
             Value::String("source_directory".to_owned()),
             Value::String(self.source_directory.borrow().to_string()),
@@ -209,7 +230,7 @@ impl WindowImpl for Window {
         );
 
         let yaml_data = serde_yaml::to_string(&yaml_mapping).unwrap();
-        let mut file = File::create(util::xdg_path().unwrap()).unwrap();
+        let mut file = File::create(config_path).unwrap();
         file.write_all(yaml_data.as_bytes()).unwrap();
         self.obj().save_settings();
         self.parent_close_request()
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+struct Bucket {
+    rate: u64,
+    available: f64,
+    updated: Instant,
+}
+
+pub struct HostSlot {
+    host: String,
+}
+
+fn bucket() -> &'static Mutex<Bucket> {
+    static BUCKET: OnceLock<Mutex<Bucket>> = OnceLock::new();
+
+    BUCKET.get_or_init(|| {
+        Mutex::new(Bucket {
+            rate: 0,
+            available: 0.0,
+            updated: Instant::now(),
+        })
+    })
+}
+
+fn slots() -> &'static (Mutex<HashMap<String, usize>>, Condvar) {
+    static SLOTS: OnceLock<(Mutex<HashMap<String, usize>>, Condvar)> = OnceLock::new();
+
+    SLOTS.get_or_init(Default::default)
+}
+
+pub fn set_rate(rate: Option<u64>) {
+    let mut bucket = bucket().lock().unwrap();
+
+    bucket.rate = rate.unwrap_or_default();
+    bucket.available = bucket.rate as f64;
+    bucket.updated = Instant::now();
+}
+
+pub fn throttle(bytes: usize) {
+    let delay = {
+        let mut bucket = bucket().lock().unwrap();
+
+        if bucket.rate == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let rate = bucket.rate as f64;
+        let refill = (now - bucket.updated).as_secs_f64() * rate;
+
+        bucket.available = (bucket.available + refill).min(rate) - bytes as f64;
+        bucket.updated = now;
+
+        if bucket.available >= 0.0 {
+            return;
+        }
+
+        Duration::from_secs_f64(-bucket.available / rate)
+    };
+
+    thread::sleep(delay);
+}
+
+impl HostSlot {
+    pub fn acquire(host: &str, limit: usize) -> Self {
+        let (active, available) = slots();
+        let mut active = available
+            .wait_while(active.lock().unwrap(), |active| {
+                active.get(host).is_some_and(|count| *count >= limit)
+            })
+            .unwrap();
+
+        *active.entry(host.to_owned()).or_default() += 1;
+
+        Self {
+            host: host.to_owned(),
+        }
+    }
+}
+
+impl Drop for HostSlot {
+    fn drop(&mut self) {
+        let (active, available) = slots();
+
+        if let Some(count) = active.lock().unwrap().get_mut(&self.host) {
+            *count -= 1;
+        }
+
+        available.notify_all();
+    }
+}
+
+#[test]
+fn test_host_slot() {
+    let slot = HostSlot::acquire("example.com", 1);
+    let waiter = thread::spawn(|| {
+        let started = Instant::now();
+        let _slot = HostSlot::acquire("example.com", 1);
+
+        started.elapsed()
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    drop(slot);
+
+    assert!(waiter.join().unwrap() >= Duration::from_millis(100));
+}
@@ -3,10 +3,16 @@
 #[cfg(feature = "cli")]
 mod cli;
 #[cfg(any(feature = "cli", feature = "gui"))]
+mod config;
+#[cfg(feature = "cli")]
+mod crypto;
+#[cfg(any(feature = "cli", feature = "gui"))]
 mod git;
 #[cfg(feature = "gui")]
 mod gui;
 #[cfg(any(feature = "cli", feature = "gui"))]
+mod limit;
+#[cfg(any(feature = "cli", feature = "gui"))]
 mod util;
 
 fn main() {
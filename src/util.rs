@@ -1,8 +1,9 @@
 use anyhow::Result;
 
 use std::{
-    env, fs,
+    env, fs, io,
     path::{Path, PathBuf},
+    process::Command,
 };
 
 mod built_info {
@@ -39,6 +40,138 @@ pub fn get_name(target: &str) -> &str {
     target.rsplit('/').next().unwrap_or(target)
 }
 
+pub fn get_host(target: &str) -> Option<&str> {
+    let authority = if let Some((_, rest)) = target.split_once("://") {
+        rest.split('/').next()?
+    } else {
+        let (authority, _) = target.split_once(':')?;
+
+        if authority.contains('/') {
+            return None;
+        }
+
+        authority
+    };
+
+    let host = authority.rsplit('@').next()?;
+    let host = if host.starts_with('[') {
+        host.split_once(']').map_or(host, |(host, _)| &host[1..])
+    } else {
+        host.split(':').next()?
+    };
+
+    (!host.is_empty()).then_some(host)
+}
+
+pub fn get_port(target: &str) -> Option<u16> {
+    let (_, rest) = target.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    let port = if host.starts_with('[') {
+        host.split_once("]:")?.1
+    } else {
+        host.split_once(':')?.1
+    };
+
+    port.parse().ok()
+}
+
+#[cfg(feature = "cli")]
+pub fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| match entry.metadata() {
+                    Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+// Civil date (UTC) from days since the Unix epoch
+#[cfg(feature = "cli")]
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month.try_into().unwrap(), day.try_into().unwrap())
+}
+
+#[cfg(feature = "cli")]
+pub fn timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_date((secs / 86400).try_into().unwrap());
+    let time = secs % 86400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(feature = "cli")]
+pub fn civil_days(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    );
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+pub fn secret_prompt(message: &str) -> Result<String> {
+    let mut line = String::new();
+    let tty = Path::new("/dev/tty").exists();
+
+    eprint!("{message}");
+
+    if tty {
+        let _ = Command::new("stty")
+            .arg("-echo")
+            .stdin(fs::File::open("/dev/tty")?)
+            .status();
+    }
+
+    let read = io::stdin().read_line(&mut line);
+
+    if tty {
+        let _ = Command::new("stty")
+            .arg("echo")
+            .stdin(fs::File::open("/dev/tty")?)
+            .status();
+    }
+
+    eprintln!();
+    read?;
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
 #[cfg(feature = "cli")]
 pub fn get_dir() -> String {
     let current_dir = env::current_dir().unwrap();
@@ -73,6 +206,32 @@ pub fn init_logs() -> tracing_appender::non_blocking::WorkerGuard {
     guard
 }
 
+#[test]
+fn test_host() {
+    assert!(get_host("https://github.com/foo/bar") == Some("github.com"));
+    assert!(get_host("https://user@example.com:8443/foo") == Some("example.com"));
+    assert!(get_host("ssh://git@[::1]:22/foo") == Some("::1"));
+    assert!(get_host("git@gitlab.com:foo/bar.git") == Some("gitlab.com"));
+    assert!(get_host("/tmp/foo:bar").is_none());
+    assert!(get_host("file:///tmp/foo").is_none());
+    assert!(get_port("ssh://git@example.com:2222/foo") == Some(2222));
+    assert!(get_port("ssh://git@[::1]:22/foo") == Some(22));
+    assert!(get_port("git@gitlab.com:foo/bar.git").is_none());
+    assert!(get_port("https://github.com/foo/bar").is_none());
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_date() {
+    assert!(civil_date(0) == (1970, 1, 1));
+    assert!(civil_date(19_782) == (2024, 2, 29));
+    assert!(civil_days("2024-02-29") == Some(19_782));
+    assert!(civil_days("1969-12-31") == Some(-1));
+    assert!(civil_days("2024-13-01").is_none());
+    assert!(civil_days("latest").is_none());
+    assert!(timestamp(1_714_564_800) == "2024-05-01T12:00:00Z");
+}
+
 #[test]
 fn test_path() {
     let path_string = "~/";
@@ -2,12 +2,19 @@
 mod cli {
     use assert_cmd::Command;
     use predicates::str::contains;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
-    use std::{env, error::Error, fs::remove_dir_all, io::Write, path::Path, thread};
+    use std::{
+        env,
+        error::Error,
+        fs::{self, read_to_string, remove_dir_all},
+        io::Write,
+        path::Path,
+        thread,
+    };
 
     use files::{CONFIG_BOOTSTRAP, CONFIG_EMPTY, CONFIG_INVALID_URL, CONFIG_MIRROR};
-    use helper::{commit, serve, test_repo};
+    use helper::{commit, redirect, serve, serve_auth, test_repo, trickle};
 
     mod files {
         pub const CONFIG_BOOTSTRAP: &[u8; 72] = b"\x2d\x2d\x2d\x0a\x73\x6f\x75\x72\x63\x65\x5f\x64\x69\x72\x65\x63\x74\x6f\x72\x79\x3a\x20\x74\x65\x73\x74\x2d\x62\x6f\x6f\x74\x73\x74\x72\x61\x70\x0a\x74\x61\x72\x67\x65\x74\x73\x3a\x0a\x20\x20\x2d\x20\x68\x74\x74\x70\x3a\x2f\x2f\x6c\x6f\x63\x61\x6c\x68\x6f\x73\x74\x3a\x37\x38\x36\x38\x0a";
@@ -22,10 +29,17 @@ mod cli {
 
     mod helper {
         use git2::{Commit, ObjectType, Repository, Signature};
-        use rouille::{cgi::CgiRun, Server};
+        use rouille::{cgi::CgiRun, Response, Server};
         use tempfile::TempDir;
 
-        use std::{fs::File, path::Path, process::Command, thread};
+        use std::{
+            fs::File,
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            path::Path,
+            process::Command,
+            thread,
+        };
 
         pub fn test_repo() -> TempDir {
             let dir = TempDir::new().unwrap();
@@ -76,6 +90,102 @@ mod cli {
             });
         }
 
+        // Rejects requests unless `header` carries `value`
+        pub fn serve_auth(dir: TempDir, port: u32, header: &'static str, value: String) {
+            let server = Server::new(format!("localhost:{port}"), move |request| {
+                if request.header(header) != Some(value.as_str()) {
+                    return Response::text("")
+                        .with_status_code(401)
+                        .with_additional_header("WWW-Authenticate", "Basic realm=\"dorst\"");
+                }
+
+                let mut cmd = Command::new("git");
+
+                cmd.arg("http-backend");
+                cmd.env("GIT_PROJECT_ROOT", dir.path());
+                cmd.start_cgi(request).unwrap()
+            })
+            .unwrap();
+
+            let (_handle, sender) = server.stoppable();
+
+            thread::spawn(move || {
+                thread::sleep(std::time::Duration::from_secs(10));
+                sender.send(()).unwrap();
+            });
+        }
+
+        pub fn redirect(port: u32, location: String) {
+            let server = Server::new(format!("localhost:{port}"), move |request| {
+                Response::redirect_301(format!("{location}{}", request.raw_url()))
+            })
+            .unwrap();
+
+            let (_handle, sender) = server.stoppable();
+
+            thread::spawn(move || {
+                thread::sleep(std::time::Duration::from_secs(10));
+                sender.send(()).unwrap();
+            });
+        }
+
+        // Advertises the repository, then only sends remote progress and never a pack
+        pub fn trickle(dir: TempDir, port: u32) {
+            let listener = TcpListener::bind(format!("localhost:{port}")).unwrap();
+
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut request = String::new();
+
+                    for line in BufReader::new(&stream).lines() {
+                        let line = line.unwrap_or_default();
+
+                        if line.is_empty() {
+                            break;
+                        }
+
+                        request.push_str(&line);
+                    }
+
+                    if request.starts_with("GET") {
+                        let mut body = b"001e# service=git-upload-pack\n0000".to_vec();
+                        let refs = Command::new("git")
+                            .args(["upload-pack", "--stateless-rpc", "--advertise-refs"])
+                            .arg(dir.path())
+                            .output()
+                            .unwrap();
+
+                        body.extend(refs.stdout);
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/x-git-upload-pack-advertisement\r\n\
+                             Content-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .unwrap();
+                        stream.write_all(&body).unwrap();
+                    } else {
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/x-git-upload-pack-result\r\n\
+                             Connection: close\r\n\r\n0008NAK\n"
+                        )
+                        .unwrap();
+
+                        for _ in 0..100 {
+                            let message = "counting\n";
+
+                            if write!(stream, "{:04x}\x02{message}", message.len() + 5).is_err() {
+                                break;
+                            }
+
+                            thread::sleep(std::time::Duration::from_millis(100));
+                        }
+                    }
+                }
+            });
+        }
+
         pub fn commit(dir: String) {
             let repo = Repository::open(dir).unwrap();
             let mut index = repo.index().unwrap();
@@ -97,7 +207,7 @@ mod cli {
             .unwrap();
         }
 
-        fn last_commit(repo: &Repository) -> Commit {
+        fn last_commit(repo: &Repository) -> Commit<'_> {
             let obj = repo
                 .head()
                 .unwrap()
@@ -207,6 +317,12 @@ mod cli {
             ));
 
         assert!(Path::new("test-mirror/localhost:7869.dorst").exists());
+        assert!(
+            git2::Repository::open("test-mirror/localhost:7869")?
+                .find_remote("origin")?
+                .url()
+                == Some("http://localhost:7869")
+        );
 
         commit(repo_dir);
         #[cfg(feature = "logs")]
@@ -229,6 +345,1372 @@ mod cli {
         Ok(())
     }
 
+    #[test]
+    fn mirror_fallback() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-mirror-fallback").exists() {
+            remove_dir_all("test-mirror-fallback")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-mirror-fallback/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        // A broken mirror must not keep the source clone from updating
+        std::fs::create_dir_all("test-mirror-fallback")?;
+        std::fs::write(
+            Path::new("test-mirror-fallback").join(format!("{repo_name}.dorst")),
+            "",
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-mirror-fallback")
+            .assert()
+            .failure()
+            .stderr(contains("backup:"));
+
+        assert!(Path::new("test-mirror-fallback/src")
+            .join(repo_name)
+            .join("foo")
+            .exists());
+
+        if Path::new("test-mirror-fallback").exists() {
+            remove_dir_all("test-mirror-fallback")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sparse() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-sparse").exists() {
+            remove_dir_all("test-sparse")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        commit(repo_dir.clone());
+        write!(
+            config,
+            "source_directory: test-sparse\ntargets:\n  - url: {repo_dir}\n    sparse:\n      - foo\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .assert()
+            .success()
+            .stdout(contains(
+                "COMPLETED\u{1b}[0m \
+             \u{1b}[37m(\u{1b}[0m\u{1b}[1;92m1\u{1b}[0m\u{1b}[37m)\u{1b}[0m",
+            ));
+
+        let clone_dir = Path::new("test-sparse").join(repo_name);
+
+        assert!(clone_dir.join("foo").exists());
+        assert!(!clone_dir.join("bar").exists());
+        assert!(clone_dir.join(".git/info/sparse-checkout").exists());
+
+        if Path::new("test-sparse").exists() {
+            remove_dir_all("test-sparse")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn retry() -> Result<(), Box<dyn Error>> {
+        let mut cmd = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-retry\ntargets:\n  - http://127.0.0.1:1/dorst\n\
+             retry:\n  attempts: 3\n  delay: 0\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        cmd.arg("--no-log");
+        cmd.arg("--config")
+            .arg(config.path())
+            .assert()
+            .failure()
+            .stderr(contains("failed after 3 attempts"));
+
+        if Path::new("test-retry").exists() {
+            remove_dir_all("test-retry")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> Result<(), Box<dyn Error>> {
+        let mut cmd = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        trickle(test_repo(), 7880);
+
+        write!(
+            config,
+            "source_directory: test-timeout\ntargets:\n  - http://localhost:7880\n\
+             timeout:\n  total: 1\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        cmd.arg("--no-log");
+        cmd.arg("--config")
+            .arg(config.path())
+            .timeout(std::time::Duration::from_secs(5))
+            .assert()
+            .failure()
+            .stderr(contains("transfer timed out (exceeded 1s)"));
+
+        if Path::new("test-timeout").exists() {
+            remove_dir_all("test-timeout")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn token() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-token").exists() {
+            remove_dir_all("test-token")?;
+        }
+
+        let home = TempDir::new()?;
+        let mut config = NamedTempFile::new()?;
+
+        serve_auth(
+            test_repo(),
+            7873,
+            "Authorization",
+            String::from("Basic b2F1dGgyOnNlY3JldA=="),
+        );
+
+        fs::write(
+            home.path().join(".gitconfig"),
+            "[credential]\n\thelper = \"!f() { echo username=oauth2; echo password=secret; }; f\"\n",
+        )?;
+        write!(
+            config,
+            "source_directory: test-token\ntoken:\n  env: DORST_TEST_TOKEN\n\
+             targets:\n  - http://localhost:7873\n"
+        )?;
+
+        for (token, home) in [("secret", None), ("expired", Some(home.path()))] {
+            let mut cmd = Command::cargo_bin("dorst")?;
+
+            // A rejected token falls back to the credential helper
+            if let Some(home) = home {
+                cmd.env("HOME", home);
+            }
+
+            #[cfg(feature = "logs")]
+            cmd.arg("--no-log");
+            cmd.env("DORST_TEST_TOKEN", token)
+                .arg("--config")
+                .arg(config.path())
+                .assert()
+                .success();
+
+            remove_dir_all("test-token")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn headers() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-headers").exists() {
+            remove_dir_all("test-headers")?;
+        }
+
+        let mut config = NamedTempFile::new()?;
+        let mut config_plain = NamedTempFile::new()?;
+
+        serve_auth(test_repo(), 7874, "X-Gateway-Token", String::from("secret"));
+
+        write!(
+            config,
+            "source_directory: test-headers\nhosts:\n  localhost:\n    headers:\n      \
+             X-Gateway-Token:\n        env: DORST_TEST_GATEWAY_TOKEN\n\
+             targets:\n  - http://localhost:7874\n"
+        )?;
+        write!(
+            config_plain,
+            "source_directory: test-headers\ntargets:\n  - http://localhost:7874\n"
+        )?;
+
+        let mut rejected = Command::cargo_bin("dorst")?;
+
+        #[cfg(feature = "logs")]
+        rejected.arg("--no-log");
+        rejected
+            .arg("--config")
+            .arg(config_plain.path())
+            .assert()
+            .failure();
+
+        let mut accepted = Command::cargo_bin("dorst")?;
+
+        #[cfg(feature = "logs")]
+        accepted.arg("--no-log");
+        accepted
+            .env("DORST_TEST_GATEWAY_TOKEN", "secret")
+            .arg("--config")
+            .arg(config.path())
+            .assert()
+            .success();
+
+        if Path::new("test-headers").exists() {
+            remove_dir_all("test-headers")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn shared() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-shared").exists() {
+            remove_dir_all("test-shared")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-shared/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("--shared")
+            .arg("test-shared")
+            .assert()
+            .success();
+
+        let clone_dir = Path::new("test-shared/src").join(repo_name);
+        let alternates = clone_dir.join(".git/objects/info/alternates");
+
+        assert!(read_to_string(alternates)?.contains(&format!("{repo_name}.dorst/objects")));
+        assert!(git2::Repository::open(clone_dir)?
+            .revparse_single("HEAD")
+            .is_ok());
+
+        if Path::new("test-shared").exists() {
+            remove_dir_all("test-shared")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn maintenance() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-maintenance").exists() {
+            remove_dir_all("test-maintenance")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-maintenance/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-maintenance")
+            .assert()
+            .success();
+
+        commit(repo_dir);
+        #[cfg(feature = "logs")]
+        fetch.arg("--no-log");
+        fetch
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("--maintenance")
+            .arg("test-maintenance")
+            .assert()
+            .success()
+            .stdout(contains("RECLAIMED"));
+
+        let mirror = Path::new("test-maintenance").join(format!("{repo_name}.dorst"));
+
+        assert!(git2::Repository::open(mirror)?
+            .config()?
+            .get_i64("dorst.maintenance")
+            .is_ok());
+
+        if Path::new("test-maintenance").exists() {
+            remove_dir_all("test-maintenance")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn maintenance_shared() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-maintenance-shared").exists() {
+            remove_dir_all("test-maintenance-shared")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+        let source = git2::Repository::open(repo.path())?;
+        let head = source.head()?.peel_to_commit()?;
+        let sig = git2::Signature::now("foo", "bar")?;
+        let feature = source.commit(
+            Some("refs/heads/feature"),
+            &sig,
+            &sig,
+            "feature",
+            &head.tree()?,
+            &[&head],
+        )?;
+
+        write!(
+            config,
+            "source_directory: test-maintenance-shared/src\nmaintenance:\n  prune: now\n\
+             targets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("--shared")
+            .arg("test-maintenance-shared")
+            .assert()
+            .success();
+
+        let clone_dir = Path::new("test-maintenance-shared/src").join(repo_name);
+
+        let clone_repo = git2::Repository::open(&clone_dir)?;
+
+        clone_repo.branch("feature", &clone_repo.find_commit(feature)?, false)?;
+        source.reference(
+            "refs/heads/feature",
+            source.commit(None, &sig, &sig, "rewritten", &head.tree()?, &[&head])?,
+            true,
+            "force-push",
+        )?;
+
+        // Without --shared, the mirror still keeps objects the clone borrows
+        #[cfg(feature = "logs")]
+        fetch.arg("--no-log");
+        fetch
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("--maintenance")
+            .arg("test-maintenance-shared")
+            .assert()
+            .success();
+
+        assert!(git2::Repository::open(&clone_dir)?
+            .find_commit(feature)
+            .is_ok());
+
+        if Path::new("test-maintenance-shared").exists() {
+            remove_dir_all("test-maintenance-shared")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-verify").exists() {
+            remove_dir_all("test-verify")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut verify = Command::cargo_bin("dorst")?;
+        let mut verify_broken = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-verify/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-verify")
+            .assert()
+            .success();
+
+        verify
+            .arg("verify")
+            .arg("test-verify")
+            .assert()
+            .success()
+            .stdout(contains(repo_name));
+
+        git2::Repository::open(Path::new("test-verify").join(format!("{repo_name}.dorst")))?
+            .config()?
+            .set_bool("remote.origin.mirror", false)?;
+
+        verify_broken
+            .arg("verify")
+            .arg("test-verify")
+            .assert()
+            .failure()
+            .stderr(contains("remote.origin.mirror is not set"));
+
+        if Path::new("test-verify").exists() {
+            remove_dir_all("test-verify")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn export() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-export").exists() {
+            remove_dir_all("test-export")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let bundles = Path::new("test-export").join("bundles");
+        let bundle = bundles.join(format!("{repo_name}.bundle"));
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut export = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-export/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-export")
+            .assert()
+            .success();
+
+        export
+            .arg("export")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--output")
+            .arg(&bundles)
+            .arg("test-export")
+            .assert()
+            .success()
+            .stdout(contains("EXPORTED"));
+
+        let checksum = read_to_string(bundles.join(format!("{repo_name}.bundle.sha256")))?;
+
+        assert!(checksum.ends_with(&format!("  {repo_name}.bundle\n")));
+        assert_eq!(checksum.split_whitespace().next().unwrap().len(), 64);
+
+        let restored = std::process::Command::new("git")
+            .arg("clone")
+            .arg("--quiet")
+            .arg(&bundle)
+            .arg(Path::new("test-export").join("restored"))
+            .status()?;
+
+        assert!(restored.success());
+        assert!(Path::new("test-export/restored/foo").exists());
+
+        if Path::new("test-export").exists() {
+            remove_dir_all("test-export")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_incremental() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-export-incremental").exists() {
+            remove_dir_all("test-export-incremental")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let bundles = Path::new("test-export-incremental").join("bundles");
+        let mut config = NamedTempFile::new()?;
+        let mut config_restore = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-export-incremental/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        write!(
+            config_restore,
+            "source_directory: test-export-incremental/restored\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        // The last round exports two commits at once
+        for round in 0..3 {
+            for _ in 0..round {
+                commit(repo_dir.clone());
+            }
+
+            let mut clone = Command::cargo_bin("dorst")?;
+            let mut export = Command::cargo_bin("dorst")?;
+
+            #[cfg(feature = "logs")]
+            clone.arg("--no-log");
+            clone
+                .arg("--config")
+                .arg(config.path())
+                .arg("--backups")
+                .arg("test-export-incremental")
+                .assert()
+                .success();
+
+            export
+                .arg("export")
+                .arg("--config")
+                .arg(config.path())
+                .arg("--incremental")
+                .arg("--output")
+                .arg(&bundles)
+                .arg("test-export-incremental")
+                .assert()
+                .success()
+                .stdout(contains("exported"));
+        }
+
+        // A new branch at an exported commit (not a previous tip) only updates the chain
+        let source = git2::Repository::open(repo.path())?;
+        let exported = source.head()?.peel_to_commit()?.parent(0)?;
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut export = Command::cargo_bin("dorst")?;
+
+        source.branch("old", &exported, false)?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-export-incremental")
+            .assert()
+            .success();
+
+        export
+            .arg("export")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--incremental")
+            .arg("--output")
+            .arg(&bundles)
+            .arg("test-export-incremental")
+            .assert()
+            .success()
+            .stdout(contains("unchanged"));
+
+        assert!(!bundles.join(format!("{repo_name}.3.bundle")).exists());
+        assert!(bundles.join(format!("{repo_name}.2.bundle")).exists());
+        assert!(
+            read_to_string(bundles.join(format!("{repo_name}.chain.yml")))?
+                .contains(&format!("file: {repo_name}.1.bundle"))
+        );
+
+        let mut restore = Command::cargo_bin("dorst")?;
+
+        restore
+            .arg("restore")
+            .arg("--config")
+            .arg(config_restore.path())
+            .arg("--bundles")
+            .arg(&bundles)
+            .arg(Path::new("test-export-incremental").join("imported"))
+            .assert()
+            .success()
+            .stdout(contains(repo_name));
+
+        let mirror = git2::Repository::open_bare(
+            Path::new("test-export-incremental")
+                .join("imported")
+                .join(format!("{repo_name}.dorst")),
+        )?;
+
+        assert_eq!(mirror.head()?.peel_to_commit()?.message(), Some("test2"),);
+        assert_eq!(
+            mirror.find_reference("refs/heads/old")?.target(),
+            Some(exported.id())
+        );
+        assert!(mirror.config()?.get_bool("remote.origin.mirror")?);
+        assert!(Path::new("test-export-incremental/restored")
+            .join(repo_name)
+            .join("bar")
+            .exists());
+
+        if Path::new("test-export-incremental").exists() {
+            remove_dir_all("test-export-incremental")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_encrypted() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-export-encrypted").exists() {
+            remove_dir_all("test-export-encrypted")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let bundles = Path::new("test-export-encrypted").join("bundles");
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut export = Command::cargo_bin("dorst")?;
+        let mut restore_denied = Command::cargo_bin("dorst")?;
+        let mut restore = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-export-encrypted/src\nencryption:\n  passphrase:\n    env: DORST_TEST_PASSPHRASE\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-export-encrypted")
+            .assert()
+            .success();
+
+        export
+            .env("DORST_TEST_PASSPHRASE", "foo")
+            .arg("export")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--output")
+            .arg(&bundles)
+            .arg("test-export-encrypted")
+            .assert()
+            .success();
+
+        assert!(bundles.join(format!("{repo_name}.bundle.enc")).exists());
+        assert!(!bundles.join(format!("{repo_name}.bundle")).exists());
+
+        remove_dir_all("test-export-encrypted/src")?;
+
+        restore_denied
+            .env("DORST_TEST_PASSPHRASE", "bar")
+            .arg("restore")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--bundles")
+            .arg(&bundles)
+            .arg(Path::new("test-export-encrypted").join("imported"))
+            .assert()
+            .failure()
+            .stderr(contains("Decryption failed"));
+
+        restore
+            .env("DORST_TEST_PASSPHRASE", "foo")
+            .arg("restore")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--bundles")
+            .arg(&bundles)
+            .arg(Path::new("test-export-encrypted").join("imported"))
+            .assert()
+            .success();
+
+        assert!(Path::new("test-export-encrypted/src")
+            .join(repo_name)
+            .join("foo")
+            .exists());
+
+        if Path::new("test-export-encrypted").exists() {
+            remove_dir_all("test-export-encrypted")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn archive() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-archive").exists() {
+            remove_dir_all("test-archive")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-archive/src\narchive:\n  format: zip\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-archive")
+            .assert()
+            .success();
+
+        let archives: Vec<String> = std::fs::read_dir("test-archive/archives")?
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].starts_with(&format!("{repo_name}-")));
+        assert!(archives[0].ends_with(".zip"));
+
+        if Path::new("test-archive").exists() {
+            remove_dir_all("test-archive")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-snapshots").exists() {
+            remove_dir_all("test-snapshots")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let snapshots = Path::new("test-snapshots").join("snapshots");
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-snapshots/src\nsnapshots:\n  daily: 1\n  weekly: 0\n  monthly: 0\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        std::fs::create_dir_all(snapshots.join("2000-01-01"))?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-snapshots")
+            .assert()
+            .success();
+
+        let generations: Vec<_> = std::fs::read_dir(&snapshots)?
+            .map(|entry| entry.unwrap().path())
+            .collect();
+
+        assert_eq!(generations.len(), 1);
+
+        let snapshot =
+            git2::Repository::open_bare(generations[0].join(format!("{repo_name}.dorst")))?;
+
+        assert_eq!(
+            snapshot.find_remote("origin")?.url(),
+            Some(repo_dir.as_str())
+        );
+        assert!(snapshot.head()?.peel_to_commit().is_ok());
+
+        if Path::new("test-snapshots").exists() {
+            remove_dir_all("test-snapshots")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-manifest").exists() {
+            remove_dir_all("test-manifest")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-manifest/src\nmanifest: {{}}\ntargets:\n  - {repo_dir}\n  - {repo_dir}-missing\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-manifest")
+            .assert()
+            .failure()
+            .stdout(contains("MANIFEST"));
+
+        let manifests: Vec<_> = std::fs::read_dir("test-manifest/manifests")?
+            .map(|entry| entry.unwrap().path())
+            .collect();
+
+        assert_eq!(manifests.len(), 1);
+
+        let manifest = read_to_string(&manifests[0])?;
+
+        assert!(manifest.contains(&format!("url: {repo_dir}\n")));
+        assert!(manifest.contains("status: ok"));
+        assert!(manifest.contains("status: error"));
+        assert!(manifest.contains("refs/heads/"));
+        assert!(manifest.contains("size: "));
+
+        if Path::new("test-manifest").exists() {
+            remove_dir_all("test-manifest")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_branch() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-default-branch").exists() {
+            remove_dir_all("test-default-branch")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-default-branch/src\ntargets:\n  - file://{repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-default-branch")
+            .assert()
+            .success();
+
+        let source = git2::Repository::open(repo.path())?;
+        let old_branch = source.head()?.shorthand().unwrap().to_owned();
+
+        source
+            .find_branch(&old_branch, git2::BranchType::Local)?
+            .rename("trunk", false)?;
+        source.set_head("refs/heads/trunk")?;
+
+        #[cfg(feature = "logs")]
+        fetch.arg("--no-log");
+        fetch
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-default-branch")
+            .assert()
+            .success();
+
+        let mirror = git2::Repository::open_bare(
+            Path::new("test-default-branch").join(format!("{repo_name}.dorst")),
+        )?;
+        let clone = git2::Repository::open(Path::new("test-default-branch/src").join(repo_name))?;
+
+        assert_eq!(
+            mirror.find_reference("HEAD")?.symbolic_target(),
+            Some("refs/heads/trunk")
+        );
+        assert_eq!(clone.head()?.shorthand(), Some(old_branch.as_str()));
+        assert_eq!(
+            clone
+                .find_reference("refs/remotes/origin/HEAD")?
+                .symbolic_target(),
+            Some("refs/remotes/origin/trunk")
+        );
+        assert_eq!(
+            clone.config()?.get_string("dorst.defaultBranch")?,
+            old_branch
+        );
+
+        if Path::new("test-default-branch").exists() {
+            remove_dir_all("test-default-branch")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn redirects() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-redirects").exists() {
+            remove_dir_all("test-redirects")?;
+        }
+
+        let repo = test_repo();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()?;
+
+        write!(
+            config,
+            "source_directory: test-redirects/src\nrewrite_redirects: true\n\
+             targets:\n  - http://localhost:7870 # moved\n"
+        )?;
+        runtime.spawn(async move {
+            serve(repo, 7871);
+            redirect(7870, String::from("http://localhost:7871"));
+        });
+
+        thread::sleep(std::time::Duration::from_millis(300));
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-redirects")
+            .assert()
+            .success();
+
+        assert!(read_to_string(config.path())?.contains("  - http://localhost:7871 # moved\n"));
+        assert!(!Path::new("test-redirects/localhost:7870.dorst").exists());
+
+        for path in [
+            "test-redirects/localhost:7871.dorst",
+            "test-redirects/src/localhost:7871",
+        ] {
+            let repo = git2::Repository::open(path)?;
+
+            assert_eq!(
+                repo.find_remote("origin")?.url(),
+                Some("http://localhost:7871")
+            );
+            assert!(repo.config()?.get_string("dorst.redirect").is_err());
+        }
+
+        if Path::new("test-redirects").exists() {
+            remove_dir_all("test-redirects")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn orphaned() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-orphaned").exists() {
+            remove_dir_all("test-orphaned")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo
+            .path()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-orphaned/src\ntargets:\n  - file://{repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-orphaned")
+            .assert()
+            .success();
+
+        repo.close()?;
+
+        #[cfg(feature = "logs")]
+        fetch.arg("--no-log");
+        fetch
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-orphaned")
+            .assert()
+            .success()
+            .stdout(contains("ORPHANED"));
+
+        let mirror = git2::Repository::open_bare(
+            Path::new("test-orphaned").join(format!("{repo_name}.dorst")),
+        )?;
+
+        assert!(mirror.config()?.get_bool("dorst.orphaned")?);
+        assert!(mirror.head()?.target().is_some());
+
+        if Path::new("test-orphaned").exists() {
+            remove_dir_all("test-orphaned")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn url_mismatch() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-url-mismatch").exists() {
+            remove_dir_all("test-url-mismatch")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch_fail = Command::cargo_bin("dorst")?;
+        let mut fetch_update = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+        let mut config_fail = NamedTempFile::new()?;
+        let mut config_update = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-url-mismatch/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+        write!(
+            config_fail,
+            "source_directory: test-url-mismatch/src\nurl_mismatch: fail\n\
+             targets:\n  - file://{repo_dir}\n"
+        )?;
+        write!(
+            config_update,
+            "source_directory: test-url-mismatch/src\ntargets:\n  - file://{repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-url-mismatch")
+            .assert()
+            .success();
+
+        #[cfg(feature = "logs")]
+        fetch_fail.arg("--no-log");
+        fetch_fail
+            .arg("--config")
+            .arg(config_fail.path())
+            .arg("--backups")
+            .arg("test-url-mismatch")
+            .assert()
+            .failure()
+            .stderr(contains("does not match"));
+
+        #[cfg(feature = "logs")]
+        fetch_update.arg("--no-log");
+        fetch_update
+            .arg("--config")
+            .arg(config_update.path())
+            .arg("--backups")
+            .arg("test-url-mismatch")
+            .assert()
+            .success();
+
+        let url = format!("file://{repo_dir}");
+
+        for path in [format!("{repo_name}.dorst"), format!("src/{repo_name}")] {
+            let repo = git2::Repository::open(Path::new("test-url-mismatch").join(path))?;
+
+            assert_eq!(repo.find_remote("origin")?.url(), Some(url.as_str()));
+        }
+
+        if Path::new("test-url-mismatch").exists() {
+            remove_dir_all("test-url-mismatch")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn push() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-push").exists() {
+            remove_dir_all("test-push")?;
+        }
+
+        let repo = test_repo();
+        let remote = tempfile::TempDir::new()?;
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let remote_dir = remote.path().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut fetch = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        git2::Repository::init_bare(remote.path().join("target.git"))?;
+        write!(
+            config,
+            "source_directory: test-push/src\npush:\n  - {remote_dir}/{{name}}.git\n\
+             targets:\n  - url: {repo_dir}\n    push:\n      - {remote_dir}/target.git\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-push")
+            .assert()
+            .success();
+
+        let source = git2::Repository::open(repo.path())?;
+        let head = source.head()?;
+
+        for name in ["target", repo_name] {
+            let pushed = git2::Repository::open_bare(remote.path().join(format!("{name}.git")))?;
+
+            assert_eq!(
+                pushed.refname_to_id(head.name().unwrap()).ok(),
+                head.target()
+            );
+        }
+
+        let pushed = git2::Repository::open_bare(remote.path().join("target.git"))?;
+
+        pushed.reference("refs/heads/stale", head.target().unwrap(), false, "test")?;
+        commit(repo_dir);
+
+        #[cfg(feature = "logs")]
+        fetch.arg("--no-log");
+        fetch
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-push")
+            .assert()
+            .success();
+
+        assert!(pushed.find_reference("refs/heads/stale").is_err());
+        assert_eq!(
+            pushed.refname_to_id(head.name().unwrap()).ok(),
+            source.refname_to_id(head.name().unwrap()).ok()
+        );
+        assert_ne!(
+            pushed.refname_to_id(head.name().unwrap()).ok(),
+            head.target()
+        );
+
+        if Path::new("test-push").exists() {
+            remove_dir_all("test-push")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn push_unreachable() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-push-unreachable").exists() {
+            remove_dir_all("test-push-unreachable")?;
+        }
+
+        let repo = test_repo();
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        write!(
+            config,
+            "source_directory: test-push-unreachable/src\npush:\n  - http://localhost:1/{{name}}.git\n\
+             targets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-push-unreachable")
+            .assert()
+            .failure()
+            .stderr(contains("push to http://localhost:1/"));
+
+        assert!(Path::new("test-push-unreachable/src")
+            .join(repo_name)
+            .join("foo")
+            .exists());
+
+        if Path::new("test-push-unreachable").exists() {
+            remove_dir_all("test-push-unreachable")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn restore() -> Result<(), Box<dyn Error>> {
+        if Path::new("test-restore").exists() {
+            remove_dir_all("test-restore")?;
+        }
+
+        let repo = test_repo();
+        let remote = tempfile::TempDir::new()?;
+        let repo_dir = String::from(repo.path().to_str().unwrap());
+        let repo_name = repo.path().file_name().unwrap().to_str().unwrap();
+        let mut clone = Command::cargo_bin("dorst")?;
+        let mut restore = Command::cargo_bin("dorst")?;
+        let mut restore_existing = Command::cargo_bin("dorst")?;
+        let mut push = Command::cargo_bin("dorst")?;
+        let mut config = NamedTempFile::new()?;
+
+        git2::Repository::init_bare(remote.path())?;
+        write!(
+            config,
+            "source_directory: test-restore/src\ntargets:\n  - {repo_dir}\n"
+        )?;
+
+        #[cfg(feature = "logs")]
+        clone.arg("--no-log");
+        clone
+            .arg("--config")
+            .arg(config.path())
+            .arg("--backups")
+            .arg("test-restore")
+            .assert()
+            .success();
+
+        remove_dir_all("test-restore/src")?;
+
+        #[cfg(feature = "logs")]
+        restore.arg("--no-log");
+        restore
+            .arg("restore")
+            .arg("--config")
+            .arg(config.path())
+            .arg("test-restore")
+            .assert()
+            .success()
+            .stdout(contains(repo_name));
+
+        let restored = git2::Repository::open(Path::new("test-restore/src").join(repo_name))?;
+
+        assert_eq!(
+            restored.find_remote("origin")?.url(),
+            Some(repo_dir.as_str())
+        );
+        assert!(restored.head()?.is_branch());
+
+        #[cfg(feature = "logs")]
+        restore_existing.arg("--no-log");
+        restore_existing
+            .arg("restore")
+            .arg("--config")
+            .arg(config.path())
+            .arg("test-restore")
+            .assert()
+            .failure()
+            .stderr(contains("already exists"));
+
+        #[cfg(feature = "logs")]
+        push.arg("--no-log");
+        push.arg("restore")
+            .arg("--config")
+            .arg(config.path())
+            .arg("--target")
+            .arg(repo_name)
+            .arg("--push")
+            .arg(remote.path())
+            .arg("test-restore")
+            .assert()
+            .success();
+
+        let source = git2::Repository::open(repo.path())?;
+        let head = source.head()?;
+        let pushed = git2::Repository::open_bare(remote.path())?;
+
+        assert_eq!(
+            pushed.refname_to_id(head.name().unwrap()).ok(),
+            head.target()
+        );
+
+        if Path::new("test-restore").exists() {
+            remove_dir_all("test-restore")?;
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn config_empty() -> Result<(), Box<dyn Error>> {
         let mut cmd = Command::cargo_bin("dorst")?;